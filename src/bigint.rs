@@ -0,0 +1,223 @@
+// Arbitrary-precision integers, behind the `bigint` cargo feature.
+//
+// `Value::BigInt` only ever holds numbers outside the i64 range. Results
+// that fit are demoted back to `Value::Int`, so every integer has a single
+// representation and `==` and map keys work across the two.
+
+use std::cmp::Ordering;
+
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+
+use crate::{
+    err::LoxError,
+    interpreter::{RuntimeResult, Value, float_binary},
+    scanner::{Token, TokenType},
+};
+
+pub use num_bigint::{BigInt, Sign};
+
+pub fn demote(n: BigInt) -> Value {
+    match n.to_i64() {
+        Some(i) => Value::Int(i),
+        None => Value::BigInt(n),
+    }
+}
+
+// Either integer representation widened to a BigInt
+pub fn as_big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Int(n) => Some(BigInt::from(*n)),
+        Value::BigInt(n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+// The float as a BigInt, if it has no fractional part
+pub fn from_float(n: f64) -> Option<BigInt> {
+    if n.fract() == 0.0 {
+        BigInt::from_f64(n)
+    } else {
+        None
+    }
+}
+
+pub fn to_float(n: &BigInt) -> f64 {
+    n.to_f64().unwrap_or(f64::NAN)
+}
+
+// Same semantics as the i64 operators in `int_binary` and `bitwise`, minus
+// the overflow.
+pub fn binary(operator: &Token, l: BigInt, r: BigInt) -> RuntimeResult {
+    let n = match operator.type_ {
+        TokenType::Plus => l + r,
+        TokenType::Minus => l - r,
+        TokenType::Star => l * r,
+        TokenType::Slash => return float_binary(operator, to_float(&l), to_float(&r)),
+        TokenType::Percent if r.is_zero() => {
+            return Err(LoxError::at_token(operator, "Modulo by zero"));
+        }
+        TokenType::Percent => floor_mod(l, r),
+        TokenType::TildeSlash if r.is_zero() => {
+            return Err(LoxError::at_token(operator, "Integer division by zero"));
+        }
+        TokenType::TildeSlash => floor_div(l, r),
+        TokenType::StarStar if r.is_negative() => {
+            return float_binary(operator, to_float(&l), to_float(&r));
+        }
+        TokenType::StarStar => pow(operator, l, r)?,
+
+        TokenType::Ampersand => l & r,
+        TokenType::Pipe => l | r,
+        TokenType::Caret => l ^ r,
+        TokenType::LessLess | TokenType::GreaterGreater => shift(operator, l, r)?,
+
+        TokenType::Greater => return Ok(Value::Bool(l > r)),
+        TokenType::GreaterEqual => return Ok(Value::Bool(l >= r)),
+        TokenType::Less => return Ok(Value::Bool(l < r)),
+        TokenType::LessEqual => return Ok(Value::Bool(l <= r)),
+
+        _ => unreachable!("Invalid binary operator"),
+    };
+    Ok(demote(n))
+}
+
+// Largest result `**` and `<<` compute, in bits. Past this a typo like
+// `10 ** 10 ** 10` would take minutes and gigabytes before it could be printed.
+const MAX_BITS: u64 = 1 << 20;
+
+fn pow(operator: &Token, l: BigInt, r: BigInt) -> Result<BigInt, LoxError> {
+    // 0, 1 and -1 stay small whatever the exponent
+    if l.bits() <= 1 {
+        let odd = r.bit(0);
+        return Ok(match l.sign() {
+            Sign::Minus if !odd => BigInt::from(1),
+            _ if r.is_zero() => BigInt::from(1),
+            _ => l,
+        });
+    }
+    match r.to_u32() {
+        Some(r) if l.bits().saturating_mul(r.into()) <= MAX_BITS => Ok(l.pow(r)),
+        _ => Err(LoxError::at_token(operator, "Exponent too large")),
+    }
+}
+
+// Takes the sign of `r` like `~/` rounds down, `%` on BigInt truncates
+fn floor_mod(l: BigInt, r: BigInt) -> BigInt {
+    let m = l % &r;
+    if !m.is_zero() && (m.sign() == Sign::Minus) != (r.sign() == Sign::Minus) {
+        m + r
+    } else {
+        m
+    }
+}
+
+fn shift(operator: &Token, l: BigInt, r: BigInt) -> Result<BigInt, LoxError> {
+    if r.is_negative() {
+        return Err(LoxError::at_token(
+            operator,
+            "Shift amount can't be negative",
+        ));
+    }
+    if operator.type_ == TokenType::GreaterGreater {
+        // `>>` rounds down, shifting every bit out leaves 0 or -1
+        return Ok(match r.to_u64() {
+            Some(r) => l >> r,
+            None if l.is_negative() => BigInt::from(-1),
+            None => BigInt::zero(),
+        });
+    }
+    if l.is_zero() {
+        return Ok(l);
+    }
+    match r.to_u64() {
+        Some(r) if l.bits().saturating_add(r) <= MAX_BITS => Ok(l << r),
+        _ => Err(LoxError::at_token(operator, "Shift amount too large")),
+    }
+}
+
+// Rounds towards negative infinity, `/` on BigInt truncates
+fn floor_div(l: BigInt, r: BigInt) -> BigInt {
+    let q = &l / &r;
+    if !(&l % &r).is_zero() && (l.sign() == Sign::Minus) != (r.sign() == Sign::Minus) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+// Exact counterpart of `cmp_int_float` in the interpreter. Every finite float
+// is an integer plus a fraction, and the integer part fits a BigInt exactly.
+pub fn cmp_float(n: &BigInt, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+        return None;
+    }
+    let Some(floor) = BigInt::from_f64(f.floor()) else {
+        // Infinite
+        return Some(if f > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        });
+    };
+    Some(n.cmp(&floor).then(if f > f.floor() {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }))
+}
+
+// Sign of a comparator result. Never zero, that would be an `Int`.
+pub fn cmp_zero(n: &BigInt) -> Ordering {
+    n.sign().cmp(&Sign::NoSign)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(n: &str) -> BigInt {
+        n.parse().unwrap()
+    }
+
+    #[test]
+    fn floor_div_and_mod_round_down() {
+        let l = big("-1180591620717411303428");
+        assert_eq!(
+            floor_div(l.clone(), big("3")),
+            big("-393530540239137101143")
+        );
+        assert_eq!(floor_mod(l.clone(), big("3")), big("1"));
+        assert_eq!(
+            floor_div(l.clone(), big("-3")),
+            big("393530540239137101142")
+        );
+        assert_eq!(floor_mod(l, big("-3")), big("-2"));
+
+        let l = big("1180591620717411303424");
+        assert_eq!(
+            floor_div(l.clone(), big("-7")),
+            big("-168655945816773043347")
+        );
+        assert_eq!(floor_mod(l.clone(), big("-7")), big("-5"));
+        assert_eq!(
+            floor_div(-l.clone(), big("7")),
+            big("-168655945816773043347")
+        );
+        assert_eq!(floor_mod(-l, big("7")), big("5"));
+    }
+
+    #[test]
+    fn float_order_is_exact() {
+        use Ordering::*;
+        // 2^70, past where floats can tell neighbouring integers apart
+        let p70 = big("1180591620717411303424");
+        let f70 = 2f64.powi(70);
+        assert_eq!(cmp_float(&p70, f70), Some(Equal));
+        assert_eq!(cmp_float(&(&p70 + 1), f70), Some(Greater));
+        assert_eq!(cmp_float(&(-&p70 - 1), -f70), Some(Less));
+        assert_eq!(cmp_float(&big("-3"), -2.5), Some(Less));
+        assert_eq!(cmp_float(&p70, f64::INFINITY), Some(Less));
+        assert_eq!(cmp_float(&p70, f64::NEG_INFINITY), Some(Greater));
+        assert_eq!(cmp_float(&p70, f64::NAN), None);
+    }
+}
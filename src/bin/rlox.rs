@@ -1,53 +1,279 @@
-use std::error::Error;
-use std::{env, fs, process};
+use std::io::Read;
+use std::{env, fmt, fs, io, panic, process, thread};
 
 use ya_rlox::interpreter::Interpreter;
-use ya_rlox::{err::LoxError, parser::Parser, scanner::Scanner};
+use ya_rlox::{
+    err::LoxError,
+    parser::{Parser, Stmt},
+    scanner::Scanner,
+};
+
+const USAGE: &str = "\
+Usage: rlox [options] [script | -] [args...]
+       rlox run <script | -> [-- args...]
+       rlox -e <code> [-- args...]
+
+With no script, rlox starts an interactive prompt. A script of `-` is read from stdin.
+
+Options:
+  -e, --eval <code>  Run <code> instead of a script file
+      --check        Scan and parse the program, but do not run it
+  -h, --help         Print this help and exit
+  -V, --version      Print version and exit";
+
+// Conventional exit codes, see sysexits(3)
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+const EX_OSERR: i32 = 71;
+const EX_IOERR: i32 = 74;
+
+const STACK_SIZE: usize = 64 * 1024 * 1024;
 
 fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
 
-    let result = match args.len() {
-        0 => run_prompt(),
-        // 0 => run_file("test.lox"),
-        // 0 => run_prompt(),
-        1 => run_file(&args[0]),
-        _ => {
-            println!("Usage: jlox [script]");
-            process::exit(1);
+    let cli = match Cli::parse(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("rlox: {e}\n\n{USAGE}");
+            process::exit(EX_USAGE);
+        }
+    };
+
+    // Scripts run on their own thread so `DEFAULT_MAX_CALL_DEPTH` nested calls
+    // fit on the stack even in debug builds, where the main thread's is too small.
+    let worker = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || match cli.command {
+            Command::Help => {
+                println!("{USAGE}");
+                Ok(())
+            }
+            Command::Version => {
+                println!("rlox {}", env!("CARGO_PKG_VERSION"));
+                Ok(())
+            }
+            Command::Prompt => run_prompt(),
+            Command::Run {
+                source,
+                script_args,
+            } => run_source(source, script_args, cli.mode),
+        });
+    let result = match worker.map(|handle| handle.join()) {
+        Ok(Ok(result)) => result,
+        Ok(Err(panic)) => panic::resume_unwind(panic),
+        Err(e) => {
+            eprintln!("rlox: can't start interpreter thread: {e}");
+            process::exit(EX_OSERR);
         }
     };
 
     if let Err(e) = result {
-        eprintln!("Error: {e}");
-        process::exit(65);
+        e.report();
+        let _ = io::Write::flush(&mut io::stdout());
+        process::exit(e.exit_code());
     }
+}
 
-    println!("Interpreter executed successfully");
+enum Command {
+    Help,
+    Version,
+    Prompt,
+    Run {
+        source: Source,
+        script_args: Vec<String>,
+    },
 }
-fn run_file(path: &str) -> Result<(), Box<dyn Error>> {
-    // todo: implement batch processing of source
-    let source = fs::read_to_string(path)?;
-    let lox = Lox::new(source);
-    lox.run()?;
 
-    Ok(())
+enum Source {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Run,
+    Check,
 }
 
-fn run_prompt() -> Result<(), Box<dyn Error>> {
-    let s_in = std::io::stdin();
+struct Cli {
+    command: Command,
+    mode: Mode,
+}
+
+impl Cli {
+    fn parse(args: Vec<String>) -> Result<Self, String> {
+        let mut args = args.into_iter().peekable();
+        let mut mode = Mode::Run;
+        let mut source: Option<Source> = None;
+
+        // `run` is optional sugar for `rlox <script>`
+        if args.peek().map(String::as_str) == Some("run") {
+            args.next();
+            let path = args.next().ok_or("`run` expects a script path")?;
+            source = Some(Self::script(path));
+
+            // Flags may also follow `run <script>`, e.g. `rlox run main.lox --check`
+            while let Some(arg) = args.peek() {
+                match arg.as_str() {
+                    "--check" => mode = Mode::Check,
+                    "--" => {
+                        args.next();
+                        break;
+                    }
+                    _ => break,
+                }
+                args.next();
+            }
+        }
+
+        while source.is_none() {
+            let Some(arg) = args.next() else {
+                break;
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Self::command(Command::Help)),
+                "-V" | "--version" => return Ok(Self::command(Command::Version)),
+                "--check" => mode = Mode::Check,
+                "-e" | "--eval" => {
+                    let code = args.next().ok_or("`-e` expects an argument")?;
+                    source = Some(Source::Inline(code));
+                    // `rlox -e <code> -- args...`
+                    if args.peek().map(String::as_str) == Some("--") {
+                        args.next();
+                    }
+                }
+                "--" => {
+                    let path = args.next().ok_or("expected a script after `--`")?;
+                    source = Some(Self::script(path));
+                }
+                flag if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option `{flag}`"));
+                }
+                _ => source = Some(Self::script(arg)),
+            }
+        }
+
+        // Whatever is left over belongs to the script itself, flags after a
+        // plain script path included.
+        let script_args: Vec<String> = args.collect();
+
+        let command = match source {
+            Some(source) => Command::Run {
+                source,
+                script_args,
+            },
+            None if mode == Mode::Check => return Err("`--check` expects a script".to_string()),
+            None => Command::Prompt,
+        };
+
+        Ok(Self { command, mode })
+    }
+
+    fn command(command: Command) -> Self {
+        Self {
+            command,
+            mode: Mode::Run,
+        }
+    }
+
+    fn script(path: String) -> Source {
+        if path == "-" {
+            Source::Stdin
+        } else {
+            Source::File(path)
+        }
+    }
+}
+
+enum Failure {
+    NoInput(String, io::Error),
+    Io(io::Error),
+    Syntax(Vec<LoxError>),
+    Runtime(LoxError),
+    // The script called `exit(code)`
+    Exit(u8),
+}
+
+impl Failure {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::NoInput(..) => EX_NOINPUT,
+            Self::Io(_) => EX_IOERR,
+            Self::Syntax(_) => EX_DATAERR,
+            Self::Runtime(_) => EX_SOFTWARE,
+            Self::Exit(code) => (*code).into(),
+        }
+    }
+
+    fn report(&self) {
+        match self {
+            // Leaving with a status is the script's business, nothing to report
+            Self::Exit(_) => {}
+            _ => eprintln!("{self}"),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoInput(path, e) => write!(f, "rlox: can't open `{path}`: {e}"),
+            Self::Io(e) => write!(f, "rlox: {e}"),
+            Self::Syntax(errors) => {
+                let lines: Vec<String> = errors.iter().map(LoxError::to_string).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            Self::Runtime(e) => write!(f, "{e}"),
+            Self::Exit(code) => write!(f, "rlox: script exited with status {code}"),
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+fn run_source(source: Source, args: Vec<String>, mode: Mode) -> Result<(), Failure> {
+    let source = match source {
+        Source::File(path) => fs::read_to_string(&path).map_err(|e| Failure::NoInput(path, e))?,
+        Source::Stdin => {
+            let mut buf = String::new();
+            io::stdin().read_to_string(&mut buf)?;
+            buf
+        }
+        Source::Inline(code) => code,
+    };
+
+    let lox = Lox::new(source, args);
+    match mode {
+        Mode::Run => lox.run(),
+        Mode::Check => lox.check().map(|_| ()),
+    }
+}
+
+fn run_prompt() -> Result<(), Failure> {
+    let s_in = io::stdin();
     loop {
         print!("> ");
         let mut line = String::new();
-        std::io::Write::flush(&mut std::io::stdout())?;
+        io::Write::flush(&mut io::stdout())?;
         let bytes_read = s_in.read_line(&mut line)?;
         if bytes_read == 0 {
             break; // EOF (Ctrl+D, Ctrl+Z)
         }
 
-        let lox = Lox::new(line);
-        if let Err(e) = lox.run() {
-            eprintln!("Runtime error: {e}")
+        let lox = Lox::new(line, Vec::new());
+        match lox.run() {
+            Ok(()) => {}
+            // `exit()` ends the whole session
+            Err(e @ Failure::Exit(_)) => return Err(e),
+            Err(e) => e.report(),
         }
     }
 
@@ -56,34 +282,36 @@ fn run_prompt() -> Result<(), Box<dyn Error>> {
 
 struct Lox {
     source: String,
+    args: Vec<String>,
 }
 
 impl Lox {
-    fn new(source: String) -> Self {
-        Self { source }
+    fn new(source: String, args: Vec<String>) -> Self {
+        Self { source, args }
     }
 
     // Running pipeline:
     // Lox owns `source`.
     // Lox passes ownership over `source` to Scanner
-    // Scanner consumes source and return Tokens back
-    // Parser consumes tokens and return AST.
-    fn run(self) -> Result<(), LoxError> {
-        println!("Source: \"{}\"", self.source);
-
-        // todo: scan could implement Iterator
-        let tokens = Scanner::new(self.source).scan_tokens();
-        println!("Tokens: {:?}", tokens);
-        // println!("{:#?}", tokens);
-        let ast = Parser::new(tokens).parse()?;
+    // Scanner consumes source and yields Tokens one at a time
+    // Parser pulls tokens and return AST.
+    fn run(self) -> Result<(), Failure> {
+        let args = self.args.clone();
+        let ast = self.check()?;
 
-        println!("Ast: {:#?}", ast);
-
-        let mut i8r = Interpreter::new(ast);
-        let res = i8r.interpret();
-
-        println!("Res: {:?}", res);
+        let mut i8r = Interpreter::with_args(ast, args);
+        i8r.interpret().map_err(|e| match e.exit {
+            Some(code) => Failure::Exit(code),
+            None => Failure::Runtime(e),
+        })
+    }
 
-        Ok(())
+    // Front half of the pipeline: everything up to, but excluding, execution.
+    // The parser pulls tokens from the scanner as it needs them.
+    fn check(self) -> Result<Vec<Stmt>, Failure> {
+        let scanner = Scanner::new(self.source);
+        Parser::from_tokens(scanner)
+            .parse()
+            .map_err(Failure::Syntax)
     }
 }
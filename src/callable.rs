@@ -1,11 +1,19 @@
-use core::fmt;
+use std::cell::RefCell;
+use std::env;
 use std::fmt::Debug;
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use indexmap::IndexMap;
+
+#[cfg(feature = "bigint")]
+use crate::bigint;
 use crate::{
+    convert::FromLox,
     env::Env,
-    interpreter::{Interpreter, RuntimeError, Value},
+    interpreter::{ControlFlow, Interpreter, MapKey, RuntimeError, Value},
     parser::Stmt,
+    scanner::Token,
 };
 
 pub trait Callable: Debug {
@@ -13,26 +21,110 @@ pub trait Callable: Debug {
     fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct LoxFunction {
-    declr: Vec<Stmt>,
+    name: Token,
+    params: Vec<Token>,
+    body: Vec<Stmt>,
 
     closure: Env,
 }
 
+impl LoxFunction {
+    pub fn new(name: Token, params: Vec<Token>, body: Vec<Stmt>, closure: Env) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            closure,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name.lexeme
+    }
+}
+
+// The closure may contain the function itself, so it's left out.
+impl Debug for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}/{}>", self.name.lexeme, self.params.len())
+    }
+}
+
 impl Callable for LoxFunction {
     fn arity(&self) -> usize {
-        return 0_usize;
+        self.params.len()
     }
 
     fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
-        Ok(Value::Nil)
+        let mut env = self.closure.clone();
+        env.push_scope();
+        for (param, arg) in self.params.iter().zip(args) {
+            env.define(param.lexeme.clone(), arg);
+        }
+
+        match interpreter.execute_block(&self.body, env)? {
+            ControlFlow::None => Ok(Value::Nil),
+            ControlFlow::Return(value) => Ok(value),
+            // A level deeper than the loops inside the body would leave the function
+            ControlFlow::Break(_) => Err(RuntimeError::runtime_error(
+                &self.name,
+                "Can't break out of a function.".to_string(),
+            )),
+            ControlFlow::Continue(_) => Err(RuntimeError::runtime_error(
+                &self.name,
+                "Can't continue out of a function.".to_string(),
+            )),
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct LoxClass {}
 
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError>;
+
+// Rust closure exposed to Lox, see `Interpreter::register_native`.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    func: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: impl Into<String>, arity: usize, func: F) -> Self
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    {
+        Self {
+            name: name.into(),
+            arity,
+            func: Box::new(func),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}/{}>", self.name, self.arity)
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        (self.func)(interpreter, args)
+    }
+}
+
 #[derive(Debug)]
 pub struct Clock;
 impl Callable for Clock {
@@ -50,3 +142,187 @@ impl Callable for Clock {
         Ok(Value::Number(now.as_secs_f64()))
     }
 }
+
+// Number of command-line arguments passed to the script.
+#[derive(Debug)]
+pub struct Argc;
+impl Callable for Argc {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        Ok(Value::Int(interpreter.args().len() as i64))
+    }
+}
+
+// All script arguments as a list of strings.
+#[derive(Debug)]
+pub struct Args;
+impl Callable for Args {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let args = interpreter.args().iter().cloned().map(Value::Str).collect();
+        Ok(Value::list(args))
+    }
+}
+
+// `argv(i)` returns the i-th script argument, or nil when out of range.
+#[derive(Debug)]
+pub struct Argv;
+impl Callable for Argv {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let i = index_arg("argv", &args, 0)?;
+        let arg = interpreter.args().get(i).cloned();
+
+        Ok(arg.map_or(Value::Nil, Value::Str))
+    }
+}
+
+// `getenv(name)` returns the environment variable, or nil when it is unset.
+#[derive(Debug)]
+pub struct Getenv;
+impl Callable for Getenv {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let name = string_arg("getenv", &args, 0)?;
+
+        Ok(env::var(name).map_or(Value::Nil, Value::Str))
+    }
+}
+
+// `exit(code)` stops the script with the given status. It unwinds out of
+// `interpret` as an error with `exit` set, the process is the host's to end.
+#[derive(Debug)]
+pub struct Exit;
+impl Callable for Exit {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let code = int_arg("exit", &args, 0)?;
+        let code = u8::try_from(code).map_err(|_| {
+            native_error(
+                "exit",
+                format!("Exit status must be between 0 and 255, got {code}."),
+            )
+        })?;
+        Err(RuntimeError::exit(code))
+    }
+}
+
+// Natives don't know the call site, so the error is attributed to the function itself.
+pub fn native_error(name: &str, msg: impl Into<String>) -> RuntimeError {
+    RuntimeError::new(0, format!("in native '{name}'"), msg)
+}
+
+// Argument helpers for natives. Each one checks the type of `args[i]` and
+// reports a runtime error naming the native `name` when it doesn't match.
+
+// Converts `args[i]` to any `FromLox` type.
+pub fn arg<T: FromLox>(name: &str, args: &[Value], i: usize) -> Result<T, RuntimeError> {
+    let value = args.get(i).cloned().unwrap_or_default();
+    T::from_lox(value).map_err(|e| native_error(name, format!("Argument {}: {}", i + 1, e.msg)))
+}
+
+pub fn number_arg(name: &str, args: &[Value], i: usize) -> Result<f64, RuntimeError> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n as f64),
+        #[cfg(feature = "bigint")]
+        Some(Value::BigInt(n)) => Ok(bigint::to_float(n)),
+        Some(Value::Number(n)) => Ok(*n),
+        other => Err(arg_error(name, i, "a number", other)),
+    }
+}
+
+pub fn int_arg(name: &str, args: &[Value], i: usize) -> Result<i64, RuntimeError> {
+    match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n),
+        other => Err(arg_error(name, i, "an integer", other)),
+    }
+}
+
+pub fn index_arg(name: &str, args: &[Value], i: usize) -> Result<usize, RuntimeError> {
+    match args.get(i) {
+        Some(Value::Int(n)) if *n >= 0 => Ok(*n as usize),
+        other => Err(arg_error(name, i, "a non-negative integer", other)),
+    }
+}
+
+pub fn string_arg<'a>(name: &str, args: &'a [Value], i: usize) -> Result<&'a str, RuntimeError> {
+    match args.get(i) {
+        Some(Value::Str(s)) => Ok(s),
+        other => Err(arg_error(name, i, "a string", other)),
+    }
+}
+
+pub fn list_arg(
+    name: &str,
+    args: &[Value],
+    i: usize,
+) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    match args.get(i) {
+        Some(Value::List(list)) => Ok(list.clone()),
+        other => Err(arg_error(name, i, "a list", other)),
+    }
+}
+
+pub fn map_arg(
+    name: &str,
+    args: &[Value],
+    i: usize,
+) -> Result<Rc<RefCell<IndexMap<MapKey, Value>>>, RuntimeError> {
+    match args.get(i) {
+        Some(Value::Map(map)) => Ok(map.clone()),
+        other => Err(arg_error(name, i, "a map", other)),
+    }
+}
+
+pub fn key_arg(name: &str, args: &[Value], i: usize) -> Result<MapKey, RuntimeError> {
+    let value = args.get(i);
+    value
+        .and_then(MapKey::from_value)
+        .ok_or_else(|| arg_error(name, i, "a string, number, bool or nil", value))
+}
+
+pub fn bool_arg(name: &str, args: &[Value], i: usize) -> Result<bool, RuntimeError> {
+    match args.get(i) {
+        Some(Value::Bool(b)) => Ok(*b),
+        other => Err(arg_error(name, i, "a bool", other)),
+    }
+}
+
+fn arg_error(name: &str, i: usize, expected: &str, got: Option<&Value>) -> RuntimeError {
+    let got = got.map_or("nothing", Value::type_name);
+    native_error(
+        name,
+        format!("Argument {} must be {expected}, got {got}.", i + 1),
+    )
+}
@@ -0,0 +1,203 @@
+// Conversions between Rust and Lox values, for natives and host code.
+//
+// `IntoLox` never fails. `FromLox` reports a runtime error naming the
+// expected and the actual type when the value doesn't fit.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "bigint")]
+use crate::bigint::{self, BigInt};
+use crate::{
+    err::LoxError,
+    interpreter::{MapKey, RuntimeError, Value},
+};
+
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+pub trait FromLox: Sized {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError>;
+}
+
+// `Value::Bool(true).to_rust::<bool>()` reads nicer than `bool::from_lox(..)`
+impl Value {
+    pub fn to_rust<T: FromLox>(self) -> Result<T, RuntimeError> {
+        T::from_lox(self)
+    }
+}
+
+pub fn conversion_error(expected: &str, got: &Value) -> RuntimeError {
+    LoxError::new(
+        0,
+        "in conversion".to_string(),
+        format!("Expected {expected}, got {}.", got.type_name()),
+    )
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl FromLox for Value {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        Ok(value)
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl FromLox for () {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nil => Ok(()),
+            other => Err(conversion_error("nil", &other)),
+        }
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Int(n) => Ok(n as f64),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => Ok(bigint::to_float(&n)),
+            Value::Number(n) => Ok(n),
+            other => Err(conversion_error("number", &other)),
+        }
+    }
+}
+
+impl IntoLox for i64 {
+    fn into_lox(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl FromLox for i64 {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Int(n) => Ok(n),
+            other => Err(conversion_error("integer", &other)),
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl IntoLox for BigInt {
+    fn into_lox(self) -> Value {
+        bigint::demote(self)
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl FromLox for BigInt {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        bigint::as_big(&value).ok_or_else(|| conversion_error("integer", &value))
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            other => Err(conversion_error("bool", &other)),
+        }
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::Str(self.to_string())
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Str(s) => Ok(s),
+            other => Err(conversion_error("string", &other)),
+        }
+    }
+}
+
+// `None` maps to nil and back
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::Nil, IntoLox::into_lox)
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nil => Ok(None),
+            other => T::from_lox(other).map(Some),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self) -> Value {
+        Value::list(self.into_iter().map(IntoLox::into_lox).collect())
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::List(list) => list.borrow().iter().cloned().map(T::from_lox).collect(),
+            other => Err(conversion_error("list", &other)),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for HashMap<String, T> {
+    fn into_lox(self) -> Value {
+        let entries = self
+            .into_iter()
+            .map(|(k, v)| (MapKey::Str(k), v.into_lox()))
+            .collect();
+        Value::map(entries)
+    }
+}
+
+// Only maps with string keys convert, anything else is reported
+impl<T: FromLox> FromLox for HashMap<String, T> {
+    fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+        let Value::Map(map) = value else {
+            return Err(conversion_error("map", &value));
+        };
+        map.borrow()
+            .iter()
+            .map(|(k, v)| match k {
+                MapKey::Str(k) => Ok((k.clone(), T::from_lox(v.clone())?)),
+                other => Err(conversion_error("string key", &other.to_value())),
+            })
+            .collect()
+    }
+}
@@ -1,21 +1,28 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::{err::LoxError, interpreter::Value, scanner::Token};
 
-#[derive(Clone, Debug)]
+type Scope = Rc<RefCell<HashMap<String, Value>>>;
+
+// Scopes are shared between clones: cloning an `Env` captures the current
+// chain (closures keep seeing later updates to it), while scopes pushed
+// afterwards stay private to the clone.
+#[derive(Clone, Debug, Default)]
 pub struct Env {
-    scopes: Vec<HashMap<String, Value>>,
+    scopes: Vec<Scope>,
 }
 
 impl Env {
     pub fn new() -> Self {
         Self {
-            scopes: vec![HashMap::new()],
+            scopes: vec![Scope::default()],
         }
     }
 
     pub fn push_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+        self.scopes.push(Scope::default());
     }
 
     pub fn pop_scope(&mut self) {
@@ -24,12 +31,15 @@ impl Env {
 
     pub fn define(&mut self, name: String, val: Value) {
         self.scopes
-            .last_mut()
+            .last()
             .expect("should ways be at least 1 scope")
+            .borrow_mut()
             .insert(name, val);
     }
+
     pub fn assign(&mut self, name: &Token, val: Value) -> Result<(), LoxError> {
-        for scope in self.scopes.iter_mut().rev() {
+        for scope in self.scopes.iter().rev() {
+            let mut scope = scope.borrow_mut();
             if scope.contains_key(&name.lexeme) {
                 scope.insert(name.lexeme.clone(), val);
                 return Ok(());
@@ -39,8 +49,12 @@ impl Env {
     }
 
     pub fn get(&self, name: &Token) -> Option<Value> {
+        self.lookup(&name.lexeme)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Value> {
         for scope in self.scopes.iter().rev() {
-            if let Some(v) = scope.get(&name.lexeme) {
+            if let Some(v) = scope.borrow().get(name) {
                 return Some(v.clone());
             }
         }
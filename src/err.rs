@@ -8,15 +8,19 @@ pub struct LoxError {
     pub line: usize,
     pub where_: String,
     pub msg: String,
+    // Set when the script called `exit(code)`. That isn't a failure, it only
+    // unwinds the interpreter like one, and the host decides what the code means.
+    pub exit: Option<u8>,
 }
 
 impl fmt::Display for LoxError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "[line {}] Error {}: {}",
-            self.line, self.where_, self.msg
-        )
+        match self.line {
+            // Scripts start at line 1. Line 0 is left on errors no script line
+            // is to blame for: the host called `Interpreter::call_value` directly.
+            0 => write!(f, "[host call] Error {}: {}", self.where_, self.msg),
+            line => write!(f, "[line {line}] Error {}: {}", self.where_, self.msg),
+        }
     }
 }
 
@@ -26,6 +30,18 @@ impl LoxError {
             line,
             where_,
             msg: msg.into(),
+            exit: None,
+        }
+    }
+
+    pub fn exit(code: u8) -> Self {
+        Self {
+            exit: Some(code),
+            ..Self::new(
+                0,
+                "exit".to_string(),
+                format!("Script exited with status {code}."),
+            )
         }
     }
 
@@ -34,7 +50,7 @@ impl LoxError {
     }
 
     pub fn report(&self) {
-        eprintln!("[line {}] Error {}: {}", self.line, self.where_, self.msg);
+        eprintln!("{self}");
     }
 }
 
@@ -1,19 +1,39 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
 use std::rc::Rc;
 
+use indexmap::IndexMap;
+
+#[cfg(feature = "bigint")]
+use crate::bigint::{self, BigInt};
 use crate::{
-    callable::{Callable, Clock, LoxClass, LoxFunction},
+    callable::{
+        Argc, Args, Argv, Callable, Clock, Exit, Getenv, LoxClass, LoxFunction, NativeFunction,
+    },
     env::Env,
     err::LoxError,
     parser::{Expr, Stmt},
     scanner::{Literal, Token, TokenType},
+    stdlib,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum Value {
+    #[default]
     Nil,
+    // Integer literals and integer arithmetic stay exact, see `int_binary`
+    Int(i64),
+    // Only for ints out of the i64 range, see `bigint`
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
     Number(f64),
     Bool(bool),
     Str(String),
+    // Shared and mutable: copies of a list value alias the same elements
+    List(Rc<RefCell<Vec<Value>>>),
+    // Keeps insertion order, shared the same way lists are
+    Map(Rc<RefCell<IndexMap<MapKey, Value>>>),
 
     Function(Rc<LoxFunction>),
     Class(Rc<LoxClass>),
@@ -21,49 +41,218 @@ pub enum Value {
     Native(Rc<dyn Callable>),
 }
 
-impl Default for Value {
-    fn default() -> Self {
-        Value::Nil
-    }
-}
-
 impl Value {
-    fn is_truthy(&self) -> bool {
+    pub fn is_truthy(&self) -> bool {
         match self {
             Self::Nil => false,
-            Self::Number(_) => true,
+            Self::Int(_) | Self::Number(_) => true,
+            #[cfg(feature = "bigint")]
+            Self::BigInt(_) => true,
             Self::Str(s) => s.is_empty(),
             Self::Bool(b) => b == &true,
+            Self::List(_) => true,
+            Self::Map(_) => true,
             Self::Function(_) => true,
             Self::Class(_) => true,
             Self::Native(_) => true,
         }
     }
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Int(_) => "int",
+            #[cfg(feature = "bigint")]
+            Self::BigInt(_) => "int",
+            Self::Number(_) => "float",
+            Self::Bool(_) => "bool",
+            Self::Str(_) => "string",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::Function(_) | Self::Native(_) => "function",
+            Self::Class(_) => "class",
+        }
+    }
+
+    pub fn list(elements: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn map(entries: IndexMap<MapKey, Value>) -> Self {
+        Self::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    pub fn as_callable(&self) -> Option<Rc<dyn Callable>> {
+        match self {
+            Self::Function(f) => Some(f.clone()),
+            Self::Native(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
     fn is_equal(&self, v: &Value) -> bool {
+        self.equals(v, &mut Vec::new())
+    }
+
+    // `comparing` holds the pairs of lists/maps being compared further up. A
+    // pair that comes around again is part of a cycle and taken as equal, what
+    // decides the result is the rest of the structure.
+    fn equals(&self, v: &Value, comparing: &mut Vec<(usize, usize)>) -> bool {
         match (self, v) {
-            (Self::Number(l), Self::Number(r)) => {
-                return l == r;
+            (Self::Int(l), Self::Int(r)) => l == r,
+            (Self::Number(l), Self::Number(r)) => l == r,
+            // Compared exactly, `2 ** 53 + 1 == 2.0 ** 53` must not hold
+            (Self::Int(i), Self::Number(n)) | (Self::Number(n), Self::Int(i)) => {
+                exact_int(*n) == Some(*i)
             }
-            (Self::Bool(l), Self::Bool(r)) => {
-                return l == r;
+            #[cfg(feature = "bigint")]
+            (Self::BigInt(l), Self::BigInt(r)) => l == r,
+            #[cfg(feature = "bigint")]
+            (Self::BigInt(b), Self::Number(n)) | (Self::Number(n), Self::BigInt(b)) => {
+                bigint::from_float(*n).as_ref() == Some(b)
             }
-            (Self::Str(l), Self::Str(r)) => {
-                return l == r;
+            (Self::Bool(l), Self::Bool(r)) => l == r,
+            (Self::Str(l), Self::Str(r)) => l == r,
+            (Self::List(l), Self::List(r)) => {
+                let pair = (Rc::as_ptr(l) as usize, Rc::as_ptr(r) as usize);
+                if Rc::ptr_eq(l, r) || comparing.contains(&pair) {
+                    return true;
+                }
+                let (l, r) = (l.borrow(), r.borrow());
+                comparing.push(pair);
+                let equal = l.len() == r.len()
+                    && l.iter().zip(r.iter()).all(|(a, b)| a.equals(b, comparing));
+                comparing.pop();
+                equal
+            }
+            (Self::Map(l), Self::Map(r)) => {
+                let pair = (Rc::as_ptr(l) as usize, Rc::as_ptr(r) as usize);
+                if Rc::ptr_eq(l, r) || comparing.contains(&pair) {
+                    return true;
+                }
+                let (l, r) = (l.borrow(), r.borrow());
+                comparing.push(pair);
+                let equal = l.len() == r.len()
+                    && l.iter()
+                        .all(|(k, v)| r.get(k).is_some_and(|other| v.equals(other, comparing)));
+                comparing.pop();
+                equal
             }
             (Self::Nil, Self::Nil) => true,
             _ => false,
         }
     }
+}
+
+// Map keys are limited to values whose `is_equal` is plain value equality,
+// so `Hash`/`Eq` on the key agree with `==` in Lox.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Bool(bool),
+    // Integral floats become `Int` keys too, so `m[1]` and `m[1.0]` agree
+    Int(i64),
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+    // Bit pattern of a float with a fractional part, or out of i64 range
+    Number(u64),
+    Str(String),
+}
+
+impl MapKey {
+    // None for values that can't be keys: collections, functions and NaN
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(Self::Nil),
+            Value::Bool(b) => Some(Self::Bool(*b)),
+            Value::Int(n) => Some(Self::Int(*n)),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => Some(Self::BigInt(n.clone())),
+            Value::Number(n) if n.is_nan() => None,
+            Value::Number(n) => Some(match exact_int(*n) {
+                Some(i) => Self::Int(i),
+                #[cfg(feature = "bigint")]
+                None => bigint::from_float(*n).map_or(Self::Number(n.to_bits()), Self::BigInt),
+                #[cfg(not(feature = "bigint"))]
+                None => Self::Number(n.to_bits()),
+            }),
+            Value::Str(s) => Some(Self::Str(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Nil => Value::Nil,
+            Self::Bool(b) => Value::Bool(*b),
+            Self::Int(n) => Value::Int(*n),
+            #[cfg(feature = "bigint")]
+            Self::BigInt(n) => Value::BigInt(n.clone()),
+            Self::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Self::Str(s) => Value::Str(s.clone()),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_value())
+    }
+}
 
-    fn to_string(&self) -> String {
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_nested(f, &mut Vec::new())
+    }
+}
+
+impl Value {
+    // `open` holds the lists/maps being printed further up, a list that
+    // contains itself prints as `[...]` where it comes around again
+    fn write_nested(&self, f: &mut fmt::Formatter<'_>, open: &mut Vec<usize>) -> fmt::Result {
         match self {
-            Self::Nil => "<NIL>".to_string(),
-            Self::Number(n) => n.to_string(),
-            Self::Bool(b) => b.to_string(),
-            Self::Str(s) => s.clone(),
-            Self::Function(_) => "Function".to_string(),
-            Self::Native(_) => "Native function".to_string(),
-            Self::Class(_) => "Class".to_string(),
+            Self::Nil => write!(f, "nil"),
+            Self::Int(n) => write!(f, "{n}"),
+            #[cfg(feature = "bigint")]
+            Self::BigInt(n) => write!(f, "{n}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Str(s) => write!(f, "{s}"),
+            Self::List(list) => {
+                let ptr = Rc::as_ptr(list) as usize;
+                if open.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                open.push(ptr);
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write_nested(f, open)?;
+                }
+                open.pop();
+                write!(f, "]")
+            }
+            Self::Map(map) => {
+                let ptr = Rc::as_ptr(map) as usize;
+                if open.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                open.push(ptr);
+                write!(f, "{{")?;
+                for (i, (k, v)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{k}: ")?;
+                    v.write_nested(f, open)?;
+                }
+                open.pop();
+                write!(f, "}}")
+            }
+            Self::Function(func) => write!(f, "<fn {}>", func.name()),
+            Self::Native(_) => write!(f, "<native fn>"),
+            Self::Class(_) => write!(f, "<class>"),
         }
     }
 }
@@ -73,11 +262,44 @@ pub struct Interpreter {
 
     globals: Env,
     env: Env,
+
+    // Command-line arguments of the running script, see `callable::Argv`
+    args: Vec<String>,
+
+    // Number of calls currently running, checked against `max_depth`
+    depth: usize,
+    max_depth: usize,
 }
 
+// Deepest call nesting before a script gets a "Stack overflow." error, unless
+// the host picks another limit with `Interpreter::set_max_call_depth`.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
 pub enum ControlFlow {
     None,
     Break(usize),
+    Continue(usize),
+    Return(Value),
+}
+
+// What a loop does with the outcome of one run of its body
+enum LoopStep {
+    Next,
+    Exit,
+    // Leave the loop and hand the signal to the enclosing statement
+    Unwind(ControlFlow),
+}
+
+impl From<ControlFlow> for LoopStep {
+    fn from(out: ControlFlow) -> Self {
+        match out {
+            ControlFlow::None | ControlFlow::Continue(1) => Self::Next,
+            ControlFlow::Break(1) => Self::Exit,
+            ControlFlow::Break(n) => Self::Unwind(ControlFlow::Break(n - 1)),
+            ControlFlow::Continue(n) => Self::Unwind(ControlFlow::Continue(n - 1)),
+            ret @ ControlFlow::Return(_) => Self::Unwind(ret),
+        }
+    }
 }
 
 pub type RuntimeError = LoxError;
@@ -85,39 +307,153 @@ pub type ExecResult = Result<ControlFlow, RuntimeError>;
 
 impl Interpreter {
     pub fn new(ast: Vec<Stmt>) -> Self {
-        let globals = Env::new();
-        let env = globals.clone();
+        Self::with_args(ast, Vec::new())
+    }
 
-        let mut interp = Self { ast, globals, env };
+    pub fn with_args(ast: Vec<Stmt>, args: Vec<String>) -> Self {
+        let mut interp = Self {
+            ast,
+            globals: Env::new(),
+            env: Env::new(),
+            args,
+            depth: 0,
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+        };
 
-        interp
-            .globals
-            .define("clock".to_string(), Value::Native(Rc::new(Clock)));
+        let globals = &mut interp.globals;
+        globals.define("clock".to_string(), Value::Native(Rc::new(Clock)));
+        globals.define("args".to_string(), Value::Native(Rc::new(Args)));
+        globals.define("argc".to_string(), Value::Native(Rc::new(Argc)));
+        globals.define("argv".to_string(), Value::Native(Rc::new(Argv)));
+        globals.define("getenv".to_string(), Value::Native(Rc::new(Getenv)));
+        globals.define("exit".to_string(), Value::Native(Rc::new(Exit)));
 
+        // Globals have to be in place before the root scope is cloned from them
+        interp.env = interp.globals.clone();
+        stdlib::install(&mut interp);
         interp
     }
 
-    pub fn interpret(mut self) -> Result<(), LoxError> {
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    // Exposes a Rust closure to scripts as a global function `name`.
+    // Registering an existing name replaces the previous definition.
+    //
+    // ```ignore
+    // interp.register_native("double", 1, |_, args| {
+    //     Ok(Value::Number(number_arg("double", &args, 0)? * 2.0))
+    // });
+    // ```
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    {
+        let native = Value::Native(Rc::new(NativeFunction::new(name, arity, func)));
+        self.globals.define(name.to_string(), native);
+    }
+
+    // Limits how deeply calls may nest, natives calling back into Lox included.
+    // The limit only helps if the thread running the interpreter has the stack
+    // to reach it, otherwise a deep recursion aborts the process instead of
+    // raising "Stack overflow.". Each level takes up to about 40 KiB in debug
+    // builds and 5 KiB in release builds, so the default of 1000 needs 40 MiB
+    // and 5 MiB respectively, more than the 2 MiB a spawned thread gets.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    // Global variable `name` as left by the script, e.g. a handler it defined.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.lookup(name)
+    }
+
+    // Invokes a Lox or native function from host code. Errors that no script
+    // line is to blame for are reported at `[host call]`, see `LoxError`.
+    //
+    // ```ignore
+    // interp.interpret()?;
+    // if let Some(handler) = interp.get_global("on_event") {
+    //     interp.call_value(&handler, vec!["started".into_lox()])?;
+    // }
+    // ```
+    pub fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> RuntimeResult {
+        self.call(callee, args, 0)
+    }
+
+    // The single dispatch point for every call, from scripts and from the host.
+    // `line` is the call site; errors raised without one (natives) are attributed to it.
+    fn call(&mut self, callee: &Value, args: Vec<Value>, line: usize) -> RuntimeResult {
+        let Some(callable) = callee.as_callable() else {
+            return Err(LoxError::new(
+                line,
+                callee.to_string(),
+                "Can only call functions and classes.",
+            ));
+        };
+        if args.len() != callable.arity() {
+            return Err(LoxError::new(
+                line,
+                callee.to_string(),
+                format!("Expected {} args but got {}.", callable.arity(), args.len()),
+            ));
+        }
+        if self.depth >= self.max_depth {
+            return Err(LoxError::new(line, callee.to_string(), "Stack overflow."));
+        }
+
+        self.depth += 1;
+        let result = callable.call(self, args);
+        self.depth -= 1;
+
+        result.map_err(|mut e| {
+            if e.line == 0 {
+                e.line = line;
+            }
+            e
+        })
+    }
+
+    // Runs the whole program. The interpreter keeps its globals afterwards,
+    // so the host can still look up and call what the script defined.
+    pub fn interpret(&mut self) -> Result<(), LoxError> {
         for stmt in self.ast.clone() {
-            self.execute(&stmt)?;
+            if let ControlFlow::Return(_) = self.execute(&stmt)? {
+                break;
+            }
         }
-        println!("Memory layout: {:?}", self.env);
         Ok(())
     }
 
+    // Executes `stmts` inside `env`, restoring the current environment afterwards.
+    pub fn execute_block(&mut self, stmts: &[Stmt], env: Env) -> ExecResult {
+        let prev = std::mem::replace(&mut self.env, env);
+        let out = self.execute_stmts(stmts);
+        self.env = prev;
+        out
+    }
+
+    fn execute_stmts(&mut self, stmts: &[Stmt]) -> ExecResult {
+        for stmt in stmts {
+            let out = self.execute(stmt)?;
+            if !matches!(out, ControlFlow::None) {
+                return Ok(out);
+            }
+        }
+        Ok(ControlFlow::None)
+    }
+
     pub fn execute(&mut self, statement: &Stmt) -> ExecResult {
         match statement {
-            Stmt::Break(e) => {
-                let n = if let Some(expr) = e {
-                    match self.evaluate(expr)? {
-                        Value::Number(num) => num as usize,
-                        _ => unimplemented!("Autocast to integer"),
-                    }
-                } else {
-                    1_usize // Default 1 Loop
-                };
+            Stmt::Break { keyword, level } => {
+                let n = self.loop_level(keyword, level)?;
                 Ok(ControlFlow::Break(n))
             }
+            Stmt::Continue { keyword, level } => {
+                let n = self.loop_level(keyword, level)?;
+                Ok(ControlFlow::Continue(n))
+            }
             Stmt::Var { name, init } => {
                 let value = match init {
                     Some(expr) => self.evaluate(expr)?,
@@ -128,27 +464,34 @@ impl Interpreter {
             }
             Stmt::Block { stmts } => {
                 self.env.push_scope();
-                let mut out = ControlFlow::None;
-                for stmt in stmts {
-                    out = self.execute(&stmt)?;
-                    if !matches!(out, ControlFlow::None) {
-                        break;
-                    }
-                }
+                let out = self.execute_stmts(stmts);
                 self.env.pop_scope();
 
-                Ok(out)
+                out
+            }
+            Stmt::Func { name, params, body } => {
+                let closure = self.env.clone();
+                let func = LoxFunction::new(name.clone(), params.clone(), body.clone(), closure);
+                self.env
+                    .define(name.lexeme.clone(), Value::Function(Rc::new(func)));
+                Ok(ControlFlow::None)
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                Ok(ControlFlow::Return(value))
             }
 
             Stmt::Print(expr) => {
-                let value = self.evaluate(expr);
-                println!("Print: {:?}", value);
+                let value = self.evaluate(expr)?;
+                println!("{value}");
 
                 Ok(ControlFlow::None)
             }
             Stmt::Expression(expr) => {
-                let value = self.evaluate(expr);
-                println!("expression {:?}", expr);
+                self.evaluate(expr)?;
 
                 Ok(ControlFlow::None)
             }
@@ -159,27 +502,66 @@ impl Interpreter {
             } => {
                 let mut out = ControlFlow::None;
                 if self.evaluate(cond)?.is_truthy() {
-                    out = self.execute(&then_br)?;
+                    out = self.execute(then_br)?;
                 } else if let Some(el) = else_br {
                     out = self.execute(el)?;
                 }
 
                 Ok(out)
             }
-            Stmt::While { cond, body } => {
+            Stmt::While {
+                cond,
+                body,
+                increment,
+            } => {
                 while self.evaluate(cond)?.is_truthy() {
-                    match self.execute(body)? {
-                        ControlFlow::None => {}
-                        ControlFlow::Break(1) => {
-                            break;
-                        }
-                        ControlFlow::Break(n) => return Ok(ControlFlow::Break(n - 1)),
+                    match LoopStep::from(self.execute(body)?) {
+                        LoopStep::Next => {}
+                        LoopStep::Exit => break,
+                        LoopStep::Unwind(out) => return Ok(out),
+                    }
+                    if let Some(inc) = increment {
+                        self.evaluate(inc)?;
                     }
                 }
                 Ok(ControlFlow::None)
             }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                let iterable = self.evaluate(iterable)?;
+                let mut iter = LoxIter::new(name, iterable)?;
+                while let Some(item) = iter.next(self, name.line)? {
+                    // A fresh scope per iteration, closures capture that iteration's value
+                    self.env.push_scope();
+                    self.env.define(name.lexeme.clone(), item);
+                    let out = self.execute(body);
+                    self.env.pop_scope();
+
+                    match LoopStep::from(out?) {
+                        LoopStep::Next => {}
+                        LoopStep::Exit => break,
+                        LoopStep::Unwind(out) => return Ok(out),
+                    }
+                }
+                Ok(ControlFlow::None)
+            }
+        }
+    }
 
-            _ => unimplemented!("Not yet {:?}", statement),
+    // Level of a `break`/`continue`, a positive integer defaulting to 1 loop
+    fn loop_level(&mut self, keyword: &Token, level: &Option<Expr>) -> Result<usize, RuntimeError> {
+        let Some(expr) = level else {
+            return Ok(1);
+        };
+        match self.evaluate(expr)? {
+            Value::Int(n) if n >= 1 => Ok(n as usize),
+            other => Err(LoxError::at_token(
+                keyword,
+                format!("Loop level must be a positive integer, got {other}."),
+            )),
         }
     }
 
@@ -189,18 +571,30 @@ impl Interpreter {
 
     pub fn evaluate(&mut self, expr: &Expr) -> RuntimeResult {
         match expr {
-            Expr::Literal(lit) => {
-                return Ok(lit.evaluate());
-            }
+            Expr::Literal(lit) => Ok(lit.evaluate()),
             Expr::Unary { operator, right } => {
                 let right = self.evaluate(right)?;
                 match (&operator.type_, right) {
+                    (TokenType::Minus, Value::Int(n)) => match n.checked_neg() {
+                        Some(n) => Ok(Value::Int(n)),
+                        None => int_overflow(operator, 0, n),
+                    },
+                    #[cfg(feature = "bigint")]
+                    (TokenType::Minus, Value::BigInt(n)) => Ok(bigint::demote(-n)),
                     (TokenType::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
                     (TokenType::Minus, _) => Err(LoxError::at_token(
                         operator,
                         "Operand of '-' must be a number",
                     )),
 
+                    (TokenType::Tilde, Value::Int(n)) => Ok(Value::Int(!n)),
+                    #[cfg(feature = "bigint")]
+                    (TokenType::Tilde, Value::BigInt(n)) => Ok(bigint::demote(!n)),
+                    (TokenType::Tilde, _) => Err(LoxError::at_token(
+                        operator,
+                        "Operand of '~' must be an integer",
+                    )),
+
                     (TokenType::Bang, Value::Bool(b)) => Ok(Value::Bool(b)),
                     (TokenType::Bang, Value::Nil) => Ok(Value::Bool(true)),
                     (TokenType::Bang, _) => Err(LoxError::at_token(
@@ -212,7 +606,7 @@ impl Interpreter {
                 }
             }
             Expr::Assign { name, value } => {
-                let val = self.evaluate(&value)?;
+                let val = self.evaluate(value)?;
                 self.env.assign(name, val.clone())?;
                 Ok(val)
             }
@@ -225,80 +619,15 @@ impl Interpreter {
                 let left = self.evaluate(left)?;
                 let right = self.evaluate(right)?;
 
-                match (&operator.type_, left, right) {
-                    (TokenType::Minus, Value::Number(l), Value::Number(r)) => {
-                        Ok(Value::Number(l - r))
-                    }
-                    (TokenType::Plus, Value::Number(l), Value::Number(r)) => {
-                        Ok(Value::Number(l + r))
-                    }
-                    (TokenType::Plus, Value::Str(l), Value::Str(r)) => {
-                        l.to_owned().push_str(r.as_str());
-                        Ok(Value::Str(l))
-                    }
-
-                    (TokenType::Plus, _, _) => Err(LoxError::at_token(
-                        operator,
-                        "Operand must be number or str",
-                    )),
-                    (TokenType::Slash, Value::Number(l), Value::Number(r)) => {
-                        Ok(Value::Number(l / r))
-                    }
-                    (TokenType::Star, Value::Number(l), Value::Number(r)) => {
-                        Ok(Value::Number(l * r))
-                    }
-
-                    (TokenType::Greater, Value::Number(l), Value::Number(r)) => {
-                        return Ok(Value::Bool(l > r));
-                    }
-                    (TokenType::GreaterEqual, Value::Number(l), Value::Number(r)) => {
-                        return Ok(Value::Bool(l >= r));
-                    }
-                    (TokenType::Less, Value::Number(l), Value::Number(r)) => {
-                        return Ok(Value::Bool(l < r));
-                    }
-                    (TokenType::LessEqual, Value::Number(l), Value::Number(r)) => {
-                        return Ok(Value::Bool(l <= r));
-                    }
-
-                    (TokenType::BangEqual, _l, _r) => {
-                        return Ok(Value::Bool(!_l.is_equal(&_r)));
-                    }
-
-                    (TokenType::EqualEqual, _l, _r) => {
-                        return Ok(Value::Bool(_l.is_equal(&_r)));
-                    }
-
-                    (
-                        TokenType::Slash
-                        | TokenType::Minus
-                        | TokenType::Star
-                        | TokenType::Less
-                        | TokenType::LessEqual
-                        | TokenType::Greater
-                        | TokenType::GreaterEqual,
-                        _,
-                        _,
-                    ) => Err(LoxError::at_token(operator, "Operand must be number")),
-
-                    _ => unreachable!("Invalid binary operator"),
-                }
-            }
-            Expr::Grouping(expr) => {
-                return self.evaluate(expr);
+                binary(operator, left, right)
             }
+            Expr::Grouping(expr) => self.evaluate(expr),
             Expr::Variable(token) => {
                 // todo: Am I really need to clone the value? I assume when the value is returned
                 // it should be operated as original
-                if let Some(v) = self.env.get(&token) {
-                    return Ok(v.clone());
-                } else {
-                    println!("Var not found: {:?}", self.env);
-                    return Err(LoxError::new(
-                        token.line,
-                        token.lexeme.clone(),
-                        "Undefined variable",
-                    ));
+                match self.env.get(token) {
+                    Some(v) => Ok(v),
+                    None => Err(LoxError::at_token(token, "Undefined variable")),
                 }
             }
             Expr::Logical {
@@ -306,17 +635,27 @@ impl Interpreter {
                 operator,
                 right,
             } => {
-                let left = self.evaluate(&left)?;
-                if operator.type_ == TokenType::Or {
-                    if left.is_truthy() {
-                        return Ok(left);
-                    }
+                let left = self.evaluate(left)?;
+                let short_circuit = match operator.type_ {
+                    TokenType::Or => left.is_truthy(),
+                    TokenType::QuestionQuestion => !matches!(left, Value::Nil),
+                    _ => !left.is_truthy(),
+                };
+                if short_circuit {
+                    return Ok(left);
+                }
+                self.evaluate(right)
+            }
+            Expr::Conditional {
+                cond,
+                then_br,
+                else_br,
+            } => {
+                if self.evaluate(cond)?.is_truthy() {
+                    self.evaluate(then_br)
                 } else {
-                    if !left.is_truthy() {
-                        return Ok(left);
-                    }
+                    self.evaluate(else_br)
                 }
-                return Ok(self.evaluate(&right)?);
             }
             Expr::Call {
                 callee,
@@ -329,18 +668,94 @@ impl Interpreter {
                 for arg in args {
                     e_args.push(self.evaluate(arg)?);
                 }
-                match e_callee {
-                    Value::Function(f) => {
-                        if e_args.len() != f.arity() {
-                            return Err(RuntimeError::at_token(
-                                paren,
-                                format!("Expected {} args but got {}.", f.arity(), e_args.len()),
-                            ));
-                        }
-                        return f.call(self, e_args);
+                self.call(&e_callee, e_args, paren.line)
+            }
+            Expr::Update {
+                target,
+                operator,
+                value,
+                postfix,
+            } => {
+                let (old, new) = match target.as_ref() {
+                    Expr::Variable(name) => {
+                        let old = self.evaluate(target)?;
+                        let new = binary(operator, old.clone(), self.evaluate(value)?)?;
+                        self.env.assign(name, new.clone())?;
+                        (old, new)
                     }
-                    _ => unimplemented!("Can only call functions and classes."),
+                    Expr::Index {
+                        object,
+                        bracket,
+                        index,
+                    } => {
+                        let object = self.evaluate(object)?;
+                        let index = self.evaluate(index)?;
+                        let old = index_get(bracket, &object, &index)?;
+                        let new = binary(operator, old.clone(), self.evaluate(value)?)?;
+                        index_set(bracket, &object, &index, new.clone())?;
+                        (old, new)
+                    }
+                    _ => unreachable!("Invalid update target"),
+                };
+                Ok(if *postfix { old } else { new })
+            }
+            Expr::Interpolation(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    out.push_str(&self.evaluate(part)?.to_string());
+                }
+                Ok(Value::Str(out))
+            }
+            Expr::Lambda {
+                keyword,
+                params,
+                body,
+            } => {
+                let name = Token::new(
+                    TokenType::Identifier,
+                    "anonymous".to_string(),
+                    None,
+                    keyword.line,
+                );
+                let func = LoxFunction::new(name, params.clone(), body.clone(), self.env.clone());
+                Ok(Value::Function(Rc::new(func)))
+            }
+            Expr::List(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.evaluate(element)?);
                 }
+                Ok(Value::list(values))
+            }
+            Expr::Map { brace, entries } => {
+                let mut map = IndexMap::with_capacity(entries.len());
+                for (key, value) in entries {
+                    let key = map_key(brace, &self.evaluate(key)?)?;
+                    let value = self.evaluate(value)?;
+                    map.insert(key, value);
+                }
+                Ok(Value::map(map))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                index_get(bracket, &object, &index)
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+                index_set(bracket, &object, &index, value.clone())?;
+                Ok(value)
             }
         }
     }
@@ -348,11 +763,376 @@ impl Interpreter {
 
 impl Expr {}
 
+// What a for-in loop walks over. Built-in collections are snapshotted up
+// front, so mutating them inside the loop doesn't affect the iteration.
+// Any zero-argument callable is an iterator too: it's called for each step
+// until it returns nil.
+enum LoxIter {
+    Values(std::vec::IntoIter<Value>),
+    Callable(Value),
+}
+
+impl LoxIter {
+    fn new(token: &Token, iterable: Value) -> Result<Self, RuntimeError> {
+        let values = match &iterable {
+            Value::List(list) => list.borrow().clone(),
+            Value::Map(map) => map.borrow().keys().map(MapKey::to_value).collect(),
+            Value::Str(s) => s.chars().map(|c| Value::Str(c.to_string())).collect(),
+            Value::Function(_) | Value::Native(_) => return Ok(Self::Callable(iterable)),
+            other => {
+                return Err(LoxError::at_token(
+                    token,
+                    format!("Can't iterate over {}.", other.type_name()),
+                ));
+            }
+        };
+        Ok(Self::Values(values.into_iter()))
+    }
+
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        line: usize,
+    ) -> Result<Option<Value>, RuntimeError> {
+        match self {
+            Self::Values(values) => Ok(values.next()),
+            Self::Callable(next) => match interpreter.call(next, Vec::new(), line)? {
+                Value::Nil => Ok(None),
+                value => Ok(Some(value)),
+            },
+        }
+    }
+}
+
+fn binary(operator: &Token, left: Value, right: Value) -> RuntimeResult {
+    match (&operator.type_, left, right) {
+        (TokenType::Plus, Value::Str(mut l), Value::Str(r)) => {
+            l.push_str(r.as_str());
+            Ok(Value::Str(l))
+        }
+
+        (TokenType::BangEqual, l, r) => Ok(Value::Bool(!l.is_equal(&r))),
+
+        (TokenType::EqualEqual, l, r) => Ok(Value::Bool(l.is_equal(&r))),
+
+        (
+            TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater,
+            l,
+            r,
+        ) => bitwise(operator, l, r),
+
+        (_, Value::Int(l), Value::Int(r)) => int_binary(operator, l, r),
+
+        // Mixing an int with a float promotes the int
+        (_, l, r) => {
+            #[cfg(feature = "bigint")]
+            if let (Some(l), Some(r)) = (bigint::as_big(&l), bigint::as_big(&r)) {
+                return bigint::binary(operator, l, r);
+            }
+            let ordered = int_float_order(&l, &r).and_then(|order| match operator.type_ {
+                TokenType::Greater => Some(order.is_gt()),
+                TokenType::GreaterEqual => Some(order.is_ge()),
+                TokenType::Less => Some(order.is_lt()),
+                TokenType::LessEqual => Some(order.is_le()),
+                _ => None,
+            });
+            if let Some(ordered) = ordered {
+                return Ok(Value::Bool(ordered));
+            }
+            match (as_float(&l), as_float(&r)) {
+                (Some(l), Some(r)) => float_binary(operator, l, r),
+                _ if operator.type_ == TokenType::Plus => Err(LoxError::at_token(
+                    operator,
+                    "Operand must be number or str",
+                )),
+                _ => Err(LoxError::at_token(operator, "Operand must be number")),
+            }
+        }
+    }
+}
+
+// Results that don't fit an i64 are errors rather than silently wrapping,
+// see `int_overflow`. `/` always divides exactly, so it goes through floats.
+fn int_binary(operator: &Token, l: i64, r: i64) -> RuntimeResult {
+    let n = match operator.type_ {
+        TokenType::Plus => l.checked_add(r),
+        TokenType::Minus => l.checked_sub(r),
+        TokenType::Star => l.checked_mul(r),
+        TokenType::Slash => return float_binary(operator, l as f64, r as f64),
+        TokenType::Percent if r == 0 => {
+            return Err(LoxError::at_token(operator, "Modulo by zero"));
+        }
+        // `i64::MIN % -1` is 0, not an overflow
+        TokenType::Percent => Some(floor_mod(l.wrapping_rem(r), r)),
+        TokenType::TildeSlash if r == 0 => {
+            return Err(LoxError::at_token(operator, "Integer division by zero"));
+        }
+        TokenType::TildeSlash => floor_div(l, r),
+        // A negative exponent gives a fraction
+        TokenType::StarStar if r < 0 => return float_binary(operator, l as f64, r as f64),
+        TokenType::StarStar => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+
+        TokenType::Greater => return Ok(Value::Bool(l > r)),
+        TokenType::GreaterEqual => return Ok(Value::Bool(l >= r)),
+        TokenType::Less => return Ok(Value::Bool(l < r)),
+        TokenType::LessEqual => return Ok(Value::Bool(l <= r)),
+
+        _ => unreachable!("Invalid binary operator"),
+    };
+    match n {
+        Some(n) => Ok(Value::Int(n)),
+        None => int_overflow(operator, l, r),
+    }
+}
+
+// With the `bigint` feature an overflowing operation is redone on big integers
+#[cfg(feature = "bigint")]
+fn int_overflow(operator: &Token, l: i64, r: i64) -> RuntimeResult {
+    bigint::binary(operator, l.into(), r.into())
+}
+
+#[cfg(not(feature = "bigint"))]
+fn int_overflow(operator: &Token, _l: i64, _r: i64) -> RuntimeResult {
+    Err(LoxError::at_token(operator, "Integer overflow"))
+}
+
+// Shifts by 64 bits or more, only big integers have room for them
+#[cfg(feature = "bigint")]
+fn wide_shift(operator: &Token, l: i64, r: i64) -> RuntimeResult {
+    bigint::binary(operator, l.into(), r.into())
+}
+
+#[cfg(not(feature = "bigint"))]
+fn wide_shift(operator: &Token, _l: i64, _r: i64) -> RuntimeResult {
+    Err(LoxError::at_token(
+        operator,
+        "Shift amount must be between 0 and 63",
+    ))
+}
+
+pub(crate) fn float_binary(operator: &Token, l: f64, r: f64) -> RuntimeResult {
+    let n = match operator.type_ {
+        TokenType::Plus => l + r,
+        TokenType::Minus => l - r,
+        TokenType::Star => l * r,
+        TokenType::Slash => l / r,
+        TokenType::Percent => {
+            // Floored like the int `%`, zero included: `-6.0 % 3` is 0, not -0
+            let m = l % r;
+            if m == 0.0 {
+                m.copysign(r)
+            } else if (m < 0.0) != (r < 0.0) {
+                m + r
+            } else {
+                m
+            }
+        }
+        TokenType::TildeSlash if r == 0.0 => {
+            return Err(LoxError::at_token(operator, "Integer division by zero"));
+        }
+        TokenType::TildeSlash => (l / r).floor(),
+        TokenType::StarStar => l.powf(r),
+
+        TokenType::Greater => return Ok(Value::Bool(l > r)),
+        TokenType::GreaterEqual => return Ok(Value::Bool(l >= r)),
+        TokenType::Less => return Ok(Value::Bool(l < r)),
+        TokenType::LessEqual => return Ok(Value::Bool(l <= r)),
+
+        _ => unreachable!("Invalid binary operator"),
+    };
+    Ok(Value::Number(n))
+}
+
+// Rounds towards negative infinity, unlike `/` on i64. None on overflow.
+// Turns a truncated remainder `m` into the floored one, which takes the sign
+// of `r`, so that `l == (l ~/ r) * r + l % r` holds
+fn floor_mod(m: i64, r: i64) -> i64 {
+    if m != 0 && (m < 0) != (r < 0) {
+        m + r
+    } else {
+        m
+    }
+}
+
+fn floor_div(l: i64, r: i64) -> Option<i64> {
+    let q = l.checked_div(r)?;
+    if l % r != 0 && (l < 0) != (r < 0) {
+        Some(q - 1)
+    } else {
+        Some(q)
+    }
+}
+
+// Bitwise operators only work on ints
+fn bitwise(operator: &Token, left: Value, right: Value) -> RuntimeResult {
+    let (&Value::Int(l), &Value::Int(r)) = (&left, &right) else {
+        #[cfg(feature = "bigint")]
+        if let (Some(l), Some(r)) = (bigint::as_big(&left), bigint::as_big(&right)) {
+            return bigint::binary(operator, l, r);
+        }
+        return Err(LoxError::at_token(operator, "Operands must be integers"));
+    };
+    let shift = u32::try_from(r).ok().filter(|r| *r < i64::BITS);
+    let n = match (&operator.type_, shift) {
+        (TokenType::Ampersand, _) => l & r,
+        (TokenType::Pipe, _) => l | r,
+        (TokenType::Caret, _) => l ^ r,
+        (TokenType::LessLess | TokenType::GreaterGreater, None) => {
+            return wide_shift(operator, l, r);
+        }
+        (TokenType::LessLess, Some(shift)) => {
+            // Bits shifted out of the i64 are an overflow like any other
+            match l << shift {
+                n if n >> shift == l => n,
+                _ => return int_overflow(operator, l, r),
+            }
+        }
+        (TokenType::GreaterGreater, Some(shift)) => l >> shift,
+        _ => unreachable!("Invalid bitwise operator"),
+    };
+    Ok(Value::Int(n))
+}
+
+// Orders an int against a float exactly, `9007199254740993 > 9007199254740992.0`
+// would be false with the int promoted. None for other operands and for NaN.
+fn int_float_order(l: &Value, r: &Value) -> Option<Ordering> {
+    match (l, r) {
+        (Value::Int(i), Value::Number(n)) => cmp_int_float(*i, *n),
+        (Value::Number(n), Value::Int(i)) => cmp_int_float(*i, *n).map(Ordering::reverse),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(b), Value::Number(n)) => bigint::cmp_float(b, *n),
+        #[cfg(feature = "bigint")]
+        (Value::Number(n), Value::BigInt(b)) => bigint::cmp_float(b, *n).map(Ordering::reverse),
+        _ => None,
+    }
+}
+
+fn cmp_int_float(i: i64, n: f64) -> Option<Ordering> {
+    if n.is_nan() {
+        return None;
+    }
+    // Integral floats in [-2^63, 2^63) convert to i64 exactly
+    let floor = n.floor();
+    let order = if floor >= i64::MAX as f64 {
+        Ordering::Less
+    } else if floor < i64::MIN as f64 {
+        Ordering::Greater
+    } else {
+        i.cmp(&(floor as i64))
+    };
+    // Equal to the integral part, the fraction makes the float the larger one
+    Some(order.then(if n > floor {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }))
+}
+
+fn as_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(n) => Some(*n as f64),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => Some(bigint::to_float(n)),
+        Value::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+// The float as an i64, if it is one exactly. `i64::MAX as f64` is 2^63,
+// which is already out of range.
+pub(crate) fn exact_int(n: f64) -> Option<i64> {
+    (n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64).then_some(n as i64)
+}
+
+fn index_get(bracket: &Token, object: &Value, index: &Value) -> RuntimeResult {
+    match object {
+        Value::List(list) => {
+            let list = list.borrow();
+            let i = list_index(bracket, index, list.len())?;
+            Ok(list[i].clone())
+        }
+        // Missing keys read as nil, `has` tells the two apart
+        Value::Map(map) => {
+            let key = map_key(bracket, index)?;
+            Ok(map.borrow().get(&key).cloned().unwrap_or_default())
+        }
+        other => Err(LoxError::at_token(
+            bracket,
+            format!("Can't index into {}.", other.type_name()),
+        )),
+    }
+}
+
+fn index_set(
+    bracket: &Token,
+    object: &Value,
+    index: &Value,
+    value: Value,
+) -> Result<(), RuntimeError> {
+    match object {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let i = list_index(bracket, index, list.len())?;
+            list[i] = value;
+            Ok(())
+        }
+        Value::Map(map) => {
+            let key = map_key(bracket, index)?;
+            map.borrow_mut().insert(key, value);
+            Ok(())
+        }
+        other => Err(LoxError::at_token(
+            bracket,
+            format!("Can't assign into {}.", other.type_name()),
+        )),
+    }
+}
+
+fn map_key(token: &Token, key: &Value) -> Result<MapKey, RuntimeError> {
+    MapKey::from_value(key).ok_or_else(|| {
+        LoxError::at_token(
+            token,
+            format!(
+                "Map keys must be strings, numbers, bools or nil, got {}.",
+                key.type_name()
+            ),
+        )
+    })
+}
+
+fn list_index(bracket: &Token, index: &Value, len: usize) -> Result<usize, RuntimeError> {
+    match index {
+        Value::Int(n) if *n >= 0 && (*n as usize) < len => Ok(*n as usize),
+        Value::Int(n) => Err(LoxError::at_token(
+            bracket,
+            format!("Index {n} out of bounds for list of length {len}."),
+        )),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => Err(LoxError::at_token(
+            bracket,
+            format!("Index {n} out of bounds for list of length {len}."),
+        )),
+        other => Err(LoxError::at_token(
+            bracket,
+            format!("List index must be an integer, got {}.", other.type_name()),
+        )),
+    }
+}
+
 pub type RuntimeResult = Result<Value, LoxError>;
 
 impl LoxError {
     pub fn at_token(token: &Token, msg: impl Into<String>) -> Self {
-        LoxError::new(token.line, token.lexeme.clone(), msg.into().as_str())
+        // `Eof` has no text to point at
+        let where_ = match token.type_ {
+            TokenType::Eof => "at end".to_string(),
+            _ => token.lexeme.clone(),
+        };
+        LoxError::new(token.line, where_, msg.into().as_str())
     }
 }
 
@@ -362,8 +1142,131 @@ impl Literal {
             Self::Nil => Value::Nil,
             Self::Boolean(v) => Value::Bool(*v),
             Self::String(v) => Value::Str(v.clone()),
-            Self::Integer(v) => Value::Number(*v as f64),
+            Self::Integer(v) => Value::Int(*v),
+            #[cfg(feature = "bigint")]
+            Self::BigInt(v) => Value::BigInt(v.clone()),
             Self::Float(v) => Value::Number(*v),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(type_: TokenType) -> Token {
+        Token::new(type_, String::new(), None, 1)
+    }
+
+    // `%` as `int_binary` computes it
+    fn modulo(l: i64, r: i64) -> i64 {
+        floor_mod(l.wrapping_rem(r), r)
+    }
+
+    #[test]
+    fn floor_div_rounds_down() {
+        assert_eq!(floor_div(7, 2), Some(3));
+        assert_eq!(floor_div(-7, 2), Some(-4));
+        assert_eq!(floor_div(7, -2), Some(-4));
+        assert_eq!(floor_div(-7, -2), Some(3));
+        assert_eq!(floor_div(-8, 2), Some(-4));
+        assert_eq!(floor_div(i64::MIN, 1), Some(i64::MIN));
+        assert_eq!(floor_div(i64::MIN, 2), Some(i64::MIN / 2));
+        assert_eq!(floor_div(i64::MAX, -1), Some(-i64::MAX));
+        // The one quotient that doesn't fit an i64
+        assert_eq!(floor_div(i64::MIN, -1), None);
+    }
+
+    #[test]
+    fn floor_mod_takes_the_divisor_sign() {
+        assert_eq!(modulo(7, 3), 1);
+        assert_eq!(modulo(-7, 3), 2);
+        assert_eq!(modulo(7, -3), -2);
+        assert_eq!(modulo(-7, -3), -1);
+        assert_eq!(modulo(6, -3), 0);
+        assert_eq!(modulo(i64::MIN, -1), 0);
+        assert_eq!(modulo(i64::MIN, i64::MAX), i64::MAX - 1);
+        assert_eq!(modulo(i64::MAX, i64::MIN), -1);
+        assert_eq!(modulo(-1, i64::MIN), -1);
+    }
+
+    #[test]
+    fn floor_div_and_mod_agree() {
+        let values = [
+            i64::MIN,
+            i64::MIN + 1,
+            -7,
+            -3,
+            -1,
+            1,
+            2,
+            3,
+            7,
+            i64::MAX - 1,
+            i64::MAX,
+        ];
+        for l in values {
+            for r in values {
+                let Some(q) = floor_div(l, r) else {
+                    assert_eq!((l, r), (i64::MIN, -1));
+                    continue;
+                };
+                let m = modulo(l, r);
+                assert_eq!(q as i128 * r as i128 + m as i128, l as i128, "{l} % {r}");
+                assert!(m == 0 || (m < 0) == (r < 0), "{l} % {r} = {m}");
+            }
+        }
+    }
+
+    #[test]
+    fn int_float_order_is_exact_past_2_pow_53() {
+        use Ordering::*;
+        let p53 = 1i64 << 53;
+        // `p53 + 1` rounds to `p53` as a float, the comparison must not
+        assert_eq!(cmp_int_float(p53 + 1, p53 as f64), Some(Greater));
+        assert_eq!(cmp_int_float(p53, p53 as f64), Some(Equal));
+        assert_eq!(cmp_int_float(p53 - 1, p53 as f64), Some(Less));
+        assert_eq!(cmp_int_float(-p53 - 1, -p53 as f64), Some(Less));
+        assert_eq!(cmp_int_float(p53 + 3, (p53 + 4) as f64), Some(Less));
+    }
+
+    #[test]
+    fn int_float_order_near_2_pow_63() {
+        use Ordering::*;
+        let p63 = 2f64.powi(63);
+        assert_eq!(cmp_int_float(i64::MAX, p63), Some(Less));
+        assert_eq!(cmp_int_float(i64::MIN, -p63), Some(Equal));
+        assert_eq!(cmp_int_float(i64::MIN, -p63 * 2.0), Some(Greater));
+        // The largest float below 2^63
+        let below = f64::from_bits(p63.to_bits() - 1);
+        assert_eq!(cmp_int_float(i64::MAX, below), Some(Greater));
+        assert_eq!(cmp_int_float(below as i64, below), Some(Equal));
+        assert_eq!(cmp_int_float(i64::MAX, f64::INFINITY), Some(Less));
+        assert_eq!(cmp_int_float(i64::MIN, f64::NEG_INFINITY), Some(Greater));
+    }
+
+    #[test]
+    fn int_float_order_with_fractions_and_nan() {
+        use Ordering::*;
+        assert_eq!(cmp_int_float(1, 1.5), Some(Less));
+        assert_eq!(cmp_int_float(2, 1.5), Some(Greater));
+        assert_eq!(cmp_int_float(-1, -1.5), Some(Greater));
+        assert_eq!(cmp_int_float(-2, -1.5), Some(Less));
+        assert_eq!(cmp_int_float(0, -0.0), Some(Equal));
+        assert_eq!(cmp_int_float(0, f64::NAN), None);
+    }
+
+    #[test]
+    fn float_modulo_is_floored() {
+        let percent = |l, r| match float_binary(&token(TokenType::Percent), l, r) {
+            Ok(Value::Number(n)) => n,
+            other => panic!("unexpected {other:?}"),
+        };
+        assert_eq!(percent(7.5, 2.0), 1.5);
+        assert_eq!(percent(-7.5, 2.0), 0.5);
+        assert_eq!(percent(7.5, -2.0), -0.5);
+        assert!(percent(-6.0, 3.0).is_sign_positive());
+        assert!(percent(6.0, -3.0).is_sign_negative());
+        assert!(percent(1.0, 0.0).is_nan());
+    }
+}
@@ -1,6 +1,10 @@
+#[cfg(feature = "bigint")]
+pub mod bigint;
 pub mod callable;
+pub mod convert;
 pub mod env;
 pub mod err;
 pub mod interpreter;
 pub mod parser;
 pub mod scanner;
+pub mod stdlib;
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use crate::err::LoxError;
 use crate::scanner::{Literal, Token, TokenType};
 
@@ -21,16 +23,56 @@ pub enum Expr {
         name: Token,
         value: Box<Expr>,
     },
+    // `and`, `or` and `??`, all short-circuiting
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    // `cond ? then_br : else_br`
+    Conditional {
+        cond: Box<Expr>,
+        then_br: Box<Expr>,
+        else_br: Box<Expr>,
+    },
     Call {
         callee: Box<Expr>,
         paren: Token,
         args: Vec<Expr>,
     },
+    List(Vec<Expr>),
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    // `target op= value`, `++target` and `target++`, where `operator` is the
+    // plain binary operator (`+` for `+=` and `++`). Sub-expressions of the
+    // target are evaluated once. A postfix update yields the old value.
+    Update {
+        target: Box<Expr>,
+        operator: Token,
+        value: Box<Expr>,
+        postfix: bool,
+    },
+    // `"a ${x} b"`, the parts are stringified and concatenated
+    Interpolation(Vec<Expr>),
+    // `fun (a, b) { ... }` or `(a, b) => expr`
+    Lambda {
+        keyword: Token,
+        params: Vec<Token>,
+        body: Vec<Stmt>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -52,43 +94,121 @@ pub enum Stmt {
     While {
         cond: Expr,
         body: Box<Stmt>,
+        // Runs after every iteration, including one cut short by `continue`
+        increment: Option<Expr>,
+    },
+    ForIn {
+        name: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
     },
     Func {
         name: Token,
         params: Vec<Token>,
         body: Vec<Stmt>,
     },
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
 
-    Break(Option<Expr>),
+    // The optional level counts how many enclosing loops to leave (or skip to the
+    // next iteration of), 1 when omitted. `break label;` naming an enclosing
+    // loop is resolved to a level by the parser.
+    Break {
+        keyword: Token,
+        level: Option<Expr>,
+    },
+    Continue {
+        keyword: Token,
+        level: Option<Expr>,
+    },
 }
 
 #[derive(Debug)]
 pub struct Ast {}
 
+pub type TokenStream = Box<dyn Iterator<Item = Result<Token, LoxError>>>;
+
 pub struct Parser {
-    tokens: Vec<Token>,
+    // Tokens are pulled on demand, the whole program is never held at once
+    tokens: TokenStream,
+    // Pulled but not consumed yet, `ahead[0]` is the current token. Never
+    // empty, see `fill`.
+    ahead: VecDeque<Token>,
+    previous: Option<Token>,
+    // Number of tokens consumed so far
     _current: usize,
+    scan_errors: Vec<LoxError>,
+    // Syntax errors so far, including ones that don't need the parser to
+    // synchronize, see `loop_level`
+    errors: Vec<LoxError>,
 
     statements: Vec<Stmt>,
+
+    // Labels of the loops enclosing the current statement, innermost last.
+    // Reset at function boundaries, since a loop can't be left from inside a function.
+    loops: Vec<Option<String>>,
+    // Label waiting to be claimed by the loop that follows `name:`
+    next_label: Option<String>,
 }
 pub type ParseResult<T> = Result<T, LoxError>;
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self {
-            tokens,
+        Self::from_tokens(tokens.into_iter().map(Ok))
+    }
+
+    // Parses straight from a token source such as `Scanner`, pulling tokens
+    // as it goes. Scan errors are reported by `parse`.
+    pub fn from_tokens<I>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = Result<Token, LoxError>>,
+        I::IntoIter: 'static,
+    {
+        let mut parser = Self {
+            tokens: Box::new(tokens.into_iter()),
+            ahead: VecDeque::new(),
+            previous: None,
             _current: 0,
+            scan_errors: Vec::new(),
+            errors: Vec::new(),
 
             statements: Vec::new(),
+
+            loops: Vec::new(),
+            next_label: None,
+        };
+        parser.fill(0);
+        parser
+    }
+
+    // Pulls until `ahead[n]` exists or the tokens run out. Scan errors are
+    // set aside. A source ending without `Eof` gets one, so `peek` always
+    // has a token to return.
+    fn fill(&mut self, n: usize) {
+        while self.ahead.len() <= n {
+            match self.tokens.next() {
+                Some(Ok(token)) => self.ahead.push_back(token),
+                Some(Err(e)) => self.scan_errors.push(e),
+                None if self.ahead.is_empty() => {
+                    let line = self.previous.as_ref().map_or(1, |t| t.line);
+                    let eof = Token::new(TokenType::Eof, String::new(), None, line);
+                    self.ahead.push_back(eof);
+                }
+                None => return,
+            }
         }
     }
 
     fn peek(&self) -> &Token {
-        &self.tokens[self._current]
+        &self.ahead[0]
     }
 
     fn prev(&self) -> &Token {
-        &self.tokens[self._current - 1]
+        self.previous
+            .as_ref()
+            .expect("prev() called before consuming a token")
     }
 
     fn is_eof(&self) -> bool {
@@ -97,7 +217,9 @@ impl Parser {
 
     fn advance(&mut self) -> &Token {
         if !self.is_eof() {
+            self.previous = self.ahead.pop_front();
             self._current += 1;
+            self.fill(0);
         }
         self.prev()
     }
@@ -106,6 +228,12 @@ impl Parser {
         !self.is_eof() && &self.peek().type_ == t
     }
 
+    // Like `check`, but `n` tokens further ahead
+    fn check_ahead(&mut self, n: usize, t: &TokenType) -> bool {
+        self.fill(n);
+        self.ahead.get(n).is_some_and(|token| &token.type_ == t)
+    }
+
     fn matches(&mut self, types: &[TokenType]) -> bool {
         if types.iter().any(|t| self.check(t)) {
             self.advance();
@@ -119,11 +247,7 @@ impl Parser {
         if self.check(t) {
             Ok(self.advance())
         } else {
-            Err(LoxError {
-                line: 1,
-                where_: "Consume:UnknownError".to_string(),
-                msg: msg.into(),
-            })
+            Err(LoxError::at_token(self.peek(), msg))
         }
     }
 }
@@ -135,7 +259,19 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> ParseResult<Expr> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
+        if self.matches(&[
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+            TokenType::PercentEqual,
+        ]) {
+            let operator = self.prev().clone();
+            let value = self.assignment()?;
+            return self.update(expr, operator, value, false);
+        }
+
         if self.matches(&[TokenType::Equal]) {
             let eq = self.prev().clone();
             let value = self.assignment()?;
@@ -147,6 +283,18 @@ impl Parser {
                         value: Box::from(value),
                     });
                 }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    return Ok(Expr::SetIndex {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    });
+                }
                 _ => {
                     return Err(LoxError::new(
                         eq.line,
@@ -159,6 +307,37 @@ impl Parser {
         Ok(expr)
     }
 
+    // Right associative: `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+    fn conditional(&mut self) -> ParseResult<Expr> {
+        let cond = self.coalesce()?;
+        if !self.matches(&[TokenType::Question]) {
+            return Ok(cond);
+        }
+        let then_br = self.assignment()?;
+        self.consume(&TokenType::Colon, "Expect ':' in conditional expression.")?;
+        let else_br = self.conditional()?;
+        Ok(Expr::Conditional {
+            cond: Box::new(cond),
+            then_br: Box::new(then_br),
+            else_br: Box::new(else_br),
+        })
+    }
+
+    // `??` binds looser than `or`
+    fn coalesce(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.or()?;
+        while self.matches(&[TokenType::QuestionQuestion]) {
+            let operator = self.prev().clone();
+            let right = self.or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
     fn equality(&mut self) -> ParseResult<Expr> {
         let mut expr = self.comparison()?;
 
@@ -176,7 +355,7 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> ParseResult<Expr> {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
         while self.matches(&[
             TokenType::Greater,
             TokenType::GreaterEqual,
@@ -184,7 +363,7 @@ impl Parser {
             TokenType::LessEqual,
         ]) {
             let operator: Token = self.prev().clone();
-            let right: Expr = self.term()?;
+            let right: Expr = self.bit_or()?;
 
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -196,6 +375,46 @@ impl Parser {
         Ok(expr)
     }
 
+    // Bitwise operators bind tighter than comparisons, so `a & 1 == 0`
+    // reads as `(a & 1) == 0`.
+    fn bit_or(&mut self) -> ParseResult<Expr> {
+        self.left_assoc(&[TokenType::Pipe], Self::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> ParseResult<Expr> {
+        self.left_assoc(&[TokenType::Caret], Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> ParseResult<Expr> {
+        self.left_assoc(&[TokenType::Ampersand], Self::shift)
+    }
+
+    fn shift(&mut self) -> ParseResult<Expr> {
+        self.left_assoc(
+            &[TokenType::LessLess, TokenType::GreaterGreater],
+            Self::term,
+        )
+    }
+
+    // `operand (op operand)*` for a left associative binary level
+    fn left_assoc(
+        &mut self,
+        types: &[TokenType],
+        operand: fn(&mut Self) -> ParseResult<Expr>,
+    ) -> ParseResult<Expr> {
+        let mut expr = operand(self)?;
+        while self.matches(types) {
+            let operator = self.prev().clone();
+            let right = operand(self)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
     fn term(&mut self) -> ParseResult<Expr> {
         let mut expr = self.factor()?;
 
@@ -214,7 +433,12 @@ impl Parser {
     fn factor(&mut self) -> ParseResult<Expr> {
         let mut expr = self.unary()?;
 
-        while self.matches(&[TokenType::Slash, TokenType::Star]) {
+        while self.matches(&[
+            TokenType::Slash,
+            TokenType::Star,
+            TokenType::Percent,
+            TokenType::TildeSlash,
+        ]) {
             let operator = self.prev().clone();
             let right = self.unary()?;
             expr = Expr::Binary {
@@ -226,8 +450,35 @@ impl Parser {
         Ok(expr)
     }
 
+    // Builds an `Expr::Update`, checking that the target can be assigned to
+    fn update(&self, target: Expr, token: Token, value: Expr, postfix: bool) -> ParseResult<Expr> {
+        if !matches!(target, Expr::Variable(_) | Expr::Index { .. }) {
+            return Err(LoxError::at_token(&token, "Invalid assignment target."));
+        }
+        let type_ = match token.type_ {
+            TokenType::PlusEqual | TokenType::PlusPlus => TokenType::Plus,
+            TokenType::MinusEqual | TokenType::MinusMinus => TokenType::Minus,
+            TokenType::StarEqual => TokenType::Star,
+            TokenType::SlashEqual => TokenType::Slash,
+            TokenType::PercentEqual => TokenType::Percent,
+            _ => unreachable!("Invalid update operator"),
+        };
+        Ok(Expr::Update {
+            target: Box::new(target),
+            operator: Token { type_, ..token },
+            value: Box::new(value),
+            postfix,
+        })
+    }
+
     fn unary(&mut self) -> ParseResult<Expr> {
-        if self.matches(&[TokenType::Bang, TokenType::Minus]) {
+        if self.matches(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.prev().clone();
+            let target = self.unary()?;
+            return self.update(target, operator, Expr::Literal(Literal::Integer(1)), false);
+        }
+
+        if self.matches(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
             let operator = self.prev().clone();
             let right = self.unary()?;
             let expr = Expr::Unary {
@@ -237,8 +488,32 @@ impl Parser {
             return Ok(expr);
         }
 
-        self.call()
-        //self.primary()
+        self.power()
+    }
+
+    // `**` is right associative and binds tighter than a unary operator on
+    // its left: `-2 ** 2` is `-(2 ** 2)`, while `2 ** -1` is allowed.
+    fn power(&mut self) -> ParseResult<Expr> {
+        let expr = self.postfix()?;
+        if self.matches(&[TokenType::StarStar]) {
+            let operator = self.prev().clone();
+            let right = self.unary()?;
+            return Ok(Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn postfix(&mut self) -> ParseResult<Expr> {
+        let expr = self.call()?;
+        if self.matches(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.prev().clone();
+            return self.update(expr, operator, Expr::Literal(Literal::Integer(1)), true);
+        }
+        Ok(expr)
     }
 
     fn call(&mut self) -> ParseResult<Expr> {
@@ -246,6 +521,16 @@ impl Parser {
         loop {
             if self.matches(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.matches(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self
+                    .consume(&TokenType::RightBracket, "Expect ']' after index.")?
+                    .clone();
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
             } else {
                 break;
             }
@@ -277,6 +562,106 @@ impl Parser {
         })
     }
 
+    // Scans ahead for `( ident, ... ) =>`, without consuming anything,
+    // to tell an arrow function from a parenthesized expression.
+    fn is_arrow_function(&mut self) -> bool {
+        if !self.check(&TokenType::LeftParen) {
+            return false;
+        }
+        let mut i = 1;
+        if !self.check_ahead(i, &TokenType::RightParen) {
+            loop {
+                if !self.check_ahead(i, &TokenType::Identifier) {
+                    return false;
+                }
+                i += 1;
+                if !self.check_ahead(i, &TokenType::Comma) {
+                    break;
+                }
+                i += 1;
+            }
+        }
+        self.check_ahead(i, &TokenType::RightParen) && self.check_ahead(i + 1, &TokenType::Arrow)
+    }
+
+    // `(a, b) => a + b` is sugar for `fun (a, b) { return a + b; }`,
+    // `(a) => { ... }` takes a block body as is.
+    fn arrow_function(&mut self) -> ParseResult<Expr> {
+        self.advance(); // consume '('
+        let params = self.parameters()?;
+        let keyword = self.advance().clone(); // consume '=>'
+
+        let body = if self.check(&TokenType::LeftBrace) {
+            self.function_body()?
+        } else {
+            vec![Stmt::Return {
+                keyword: keyword.clone(),
+                value: Some(self.expression()?),
+            }]
+        };
+
+        Ok(Expr::Lambda {
+            keyword,
+            params,
+            body,
+        })
+    }
+
+    // `[a, b, c]`, a trailing comma is allowed
+    fn list(&mut self) -> ParseResult<Expr> {
+        let mut elements = Vec::new();
+        while !self.check(&TokenType::RightBracket) {
+            elements.push(self.expression()?);
+            if !self.matches(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(&TokenType::RightBracket, "Expect ']' after list elements.")?;
+        Ok(Expr::List(elements))
+    }
+
+    // Interpolation segments alternate with expressions up to the closing
+    // String segment. Empty segments are left out.
+    fn interpolation(&mut self) -> ParseResult<Expr> {
+        let mut parts = Vec::new();
+        loop {
+            if let Some(Literal::String(segment)) = &self.prev().literal
+                && !segment.is_empty()
+            {
+                parts.push(Expr::Literal(Literal::String(segment.clone())));
+            }
+            if self.prev().type_ == TokenType::String {
+                return Ok(Expr::Interpolation(parts));
+            }
+
+            parts.push(self.expression()?);
+            if !self.matches(&[TokenType::Interpolation, TokenType::String]) {
+                let token = self.peek();
+                return Err(LoxError::at_token(
+                    token,
+                    "Expect '}' after interpolated expression.",
+                ));
+            }
+        }
+    }
+
+    // `{key: value, ...}`, a trailing comma is allowed
+    fn map(&mut self) -> ParseResult<Expr> {
+        let brace = self.prev().clone();
+        let mut entries = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let key = self.expression()?;
+            self.consume(&TokenType::Colon, "Expect ':' after map key.")?;
+            let value = self.expression()?;
+            entries.push((key, value));
+            if !self.matches(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(&TokenType::RightBrace, "Expect '}' after map entries.")?;
+        Ok(Expr::Map { brace, entries })
+    }
+
     fn or(&mut self) -> ParseResult<Expr> {
         let mut expr = self.and()?;
 
@@ -322,17 +707,37 @@ impl Parser {
 
             return match prev.literal {
                 Some(l) => Ok(Expr::Literal(l)),
-                None => Err(LoxError {
-                    line: prev.line,
-                    where_: "ParseError".to_string(),
-                    msg: "No literal value".to_string(),
-                }),
+                None => Err(LoxError::new(
+                    prev.line,
+                    "ParseError".to_string(),
+                    "No literal value",
+                )),
             };
         }
 
+        if self.matches(&[TokenType::Interpolation]) {
+            return self.interpolation();
+        }
+
+        if self.matches(&[TokenType::Fun]) {
+            let keyword = self.prev().clone();
+            self.consume(&TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+            let params = self.parameters()?;
+            let body = self.function_body()?;
+            return Ok(Expr::Lambda {
+                keyword,
+                params,
+                body,
+            });
+        }
+
+        if self.is_arrow_function() {
+            return self.arrow_function();
+        }
+
         if self.matches(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
-            let _ = self.consume(&TokenType::RightParen, "Expect ')' after expression.");
+            self.consume(&TokenType::RightParen, "Expect ')' after expression.")?;
 
             // todo: add validation if its already inside the grouping.
 
@@ -344,76 +749,139 @@ impl Parser {
             return Ok(Expr::Variable(self.prev().clone()));
         }
 
+        if self.matches(&[TokenType::LeftBracket]) {
+            return self.list();
+        }
+
+        // In statement position `{` always opens a block (see `statement`),
+        // so here it can only be a map literal.
+        if self.matches(&[TokenType::LeftBrace]) {
+            return self.map();
+        }
+
         let token = self.peek();
 
-        Err(LoxError {
-            line: token.line,
-            where_: format!("Unexpected token `{}` at: {}", token.lexeme, self._current),
-            msg: "ExpectedExpression".to_string(),
-        })
+        Err(LoxError::new(
+            token.line,
+            format!("Unexpected token `{}` at: {}", token.lexeme, self._current),
+            "ExpectedExpression",
+        ))
     }
 }
 
 // Statements and declarations
 impl Parser {
-    pub fn parse(mut self) -> ParseResult<Vec<Stmt>> {
+    // Parses the whole program. On error the parser synchronizes to the next
+    // statement boundary and keeps going, so every syntax error gets reported.
+    pub fn parse(mut self) -> Result<Vec<Stmt>, Vec<LoxError>> {
         while !self.is_eof() {
-            let stmt = self.declaration();
-            self.statements.push(stmt);
+            match self.declaration() {
+                Ok(stmt) => self.statements.push(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+        // Tokens lost to a scan error usually cause parse errors of their own,
+        // only the scan errors are worth reporting then
+        if !self.scan_errors.is_empty() {
+            return Err(self.scan_errors);
+        }
+        if self.errors.is_empty() {
+            Ok(self.statements)
+        } else {
+            Err(self.errors)
         }
-        Ok(self.statements)
     }
 
-    fn declaration(&mut self) -> Stmt {
-        if self.matches(&[TokenType::Fun]) {
-            return self.fun_declaration();
+    fn declaration(&mut self) -> ParseResult<Stmt> {
+        // The scanner attaches `///` comments to the `fun`/`var` keyword,
+        // declarations keep them on the declared name
+        let doc = self.peek().doc.clone();
+
+        // Without a name, `fun` starts a lambda in an expression statement
+        if self.check(&TokenType::Fun) && self.check_ahead(1, &TokenType::Identifier) {
+            self.advance();
+            return self.fun_declaration(doc);
         }
         if self.matches(&[TokenType::Var]) {
-            return self.var_declaration();
+            return self.var_declaration(doc);
         }
-        return self.statement();
+        self.statement()
     }
 
-    fn fun_declaration(&mut self) -> Stmt {
-        let name = self
-            .consume(&TokenType::Identifier, "Expect function name.")
-            .unwrap()
+    fn fun_declaration(&mut self, doc: Option<String>) -> ParseResult<Stmt> {
+        let mut name = self
+            .consume(&TokenType::Identifier, "Expect function name.")?
             .clone();
-        _ = self.consume(
+        name.doc = doc;
+        self.consume(
             &TokenType::LeftParen,
             "Expect '(' after function declaration",
-        );
+        )?;
+        let params = self.parameters()?;
+        let body = self.function_body()?;
 
-        let args: Vec<Token> = Vec::new();
-        let stmts = self.block();
+        Ok(Stmt::Func { name, params, body })
+    }
 
-        Stmt::Func {
-            name: name,
-            params: args,
-            body: stmts,
+    // Parameter list after the opening paren, up to and including ')'
+    fn parameters(&mut self) -> ParseResult<Vec<Token>> {
+        let mut params: Vec<Token> = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(LoxError::at_token(
+                        self.peek(),
+                        "Can't have more than 255 parameters.",
+                    ));
+                }
+                let param = self
+                    .consume(&TokenType::Identifier, "Expect parameter name.")?
+                    .clone();
+                params.push(param);
+                if !self.matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
+        self.consume(&TokenType::RightParen, "Expect ')' after parameters.")?;
+        Ok(params)
     }
 
-    fn var_declaration(&mut self) -> Stmt {
-        let name = self
-            .consume(&TokenType::Identifier, "Expect variable name")
-            .unwrap()
+    fn function_body(&mut self) -> ParseResult<Vec<Stmt>> {
+        self.consume(&TokenType::LeftBrace, "Expect '{' before function body.")?;
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        let stmts = self.block();
+        self.loops = enclosing_loops;
+        stmts
+    }
+
+    fn var_declaration(&mut self, doc: Option<String>) -> ParseResult<Stmt> {
+        let mut name = self
+            .consume(&TokenType::Identifier, "Expect variable name")?
             .clone();
+        name.doc = doc;
         let mut initializer: Option<Expr> = None;
 
         if self.matches(&[TokenType::Equal]) {
-            initializer = Some(self.expression().unwrap());
+            initializer = Some(self.expression()?);
         }
 
-        _ = self.consume(&TokenType::Semicolon, "Expect ';' after expression.");
+        self.consume(&TokenType::Semicolon, "Expect ';' after expression.")?;
 
-        Stmt::Var {
+        Ok(Stmt::Var {
             name,
             init: initializer,
-        }
+        })
     }
 
-    fn statement(&mut self) -> Stmt {
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        if self.check(&TokenType::Identifier) && self.check_ahead(1, &TokenType::Colon) {
+            return self.labeled_statement();
+        }
+
         if self.matches(&[TokenType::If]) {
             return self.if_statement();
         }
@@ -422,10 +890,18 @@ impl Parser {
             return self.print_statement();
         }
 
+        if self.matches(&[TokenType::Return]) {
+            return self.return_statement();
+        }
+
         if self.matches(&[TokenType::Break]) {
             return self.break_statement();
         }
 
+        if self.matches(&[TokenType::Continue]) {
+            return self.continue_statement();
+        }
+
         if self.matches(&[TokenType::While]) {
             return self.while_statement();
         }
@@ -435,78 +911,173 @@ impl Parser {
         }
 
         if self.matches(&[TokenType::LeftBrace]) {
-            return Stmt::Block {
-                stmts: self.block(),
-            };
+            return Ok(Stmt::Block {
+                stmts: self.block()?,
+            });
         }
 
-        return self.expression_statement();
+        self.expression_statement()
     }
 
-    fn break_statement(&mut self) -> Stmt {
-        let value = if self.matches(&[TokenType::Number]) {
-            let value = self.expression().unwrap();
-            Some(value)
-        } else {
+    fn return_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.prev().clone();
+        let value = if self.check(&TokenType::Semicolon) {
             None
+        } else {
+            Some(self.expression()?)
         };
-        _ = self.consume(&TokenType::Semicolon, "Expect ';' after break.");
-        Stmt::Break(value)
+        self.consume(&TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
     }
 
-    fn while_statement(&mut self) -> Stmt {
-        _ = self.consume(&TokenType::LeftParen, "Expect '(' after 'while'");
-        let cond = self.expression().unwrap();
-        _ = self.consume(&TokenType::RightParen, "Expect ')' after while condition");
+    // `name: while (...) ...`, only loops can carry a label
+    fn labeled_statement(&mut self) -> ParseResult<Stmt> {
+        let label = self.advance().clone();
+        self.advance(); // consume ':'
 
+        if !self.check(&TokenType::While) && !self.check(&TokenType::For) {
+            return Err(LoxError::at_token(&label, "Only loops can be labeled."));
+        }
+        if self.loops.contains(&Some(label.lexeme.clone())) {
+            return Err(LoxError::at_token(
+                &label,
+                "Label is already used by an enclosing loop.",
+            ));
+        }
+        self.next_label = Some(label.lexeme);
+        self.statement()
+    }
+
+    // Parses a loop body with the loop registered as the innermost one
+    fn loop_body(&mut self, label: Option<String>) -> ParseResult<Stmt> {
+        self.loops.push(label);
         let body = self.statement();
+        self.loops.pop();
+        body
+    }
 
-        Stmt::While {
-            cond: cond,
-            body: Box::new(body),
+    fn break_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.prev().clone();
+        let level = self.loop_level(&keyword)?;
+        self.consume(&TokenType::Semicolon, "Expect ';' after break.")?;
+        Ok(Stmt::Break { keyword, level })
+    }
+
+    fn continue_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.prev().clone();
+        let level = self.loop_level(&keyword)?;
+        self.consume(&TokenType::Semicolon, "Expect ';' after continue.")?;
+        Ok(Stmt::Continue { keyword, level })
+    }
+
+    // The level after `break`/`continue`: nothing, the label of an enclosing
+    // loop, or any other expression, which is evaluated at runtime. A bare
+    // identifier is always a label, a level held in a variable is written
+    // `break (n);`. Misplaced levels and unknown labels are recorded without
+    // unwinding, the statement itself parses fine.
+    fn loop_level(&mut self, keyword: &Token) -> ParseResult<Option<Expr>> {
+        if self.loops.is_empty() {
+            self.errors.push(LoxError::at_token(
+                keyword,
+                format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            ));
+        }
+        if self.check(&TokenType::Semicolon) {
+            return Ok(None);
         }
+        if self.check(&TokenType::Identifier) && self.check_ahead(1, &TokenType::Semicolon) {
+            let label = self.advance().clone();
+            return match self.label_depth(&label.lexeme) {
+                Some(depth) => Ok(Some(Expr::Literal(Literal::Integer(depth as i64)))),
+                None => {
+                    // Outside of any loop that error has been reported already
+                    if !self.loops.is_empty() {
+                        self.errors.push(LoxError::at_token(
+                            &label,
+                            format!("Undefined loop label '{}'.", label.lexeme),
+                        ));
+                    }
+                    Ok(None)
+                }
+            };
+        }
+
+        let level = self.expression()?;
+        if let Expr::Literal(Literal::Integer(n)) = level
+            && !self.loops.is_empty()
+            && n > self.loops.len() as i64
+        {
+            self.errors.push(LoxError::at_token(
+                keyword,
+                format!(
+                    "Can't leave {n} loops, only {} enclosing.",
+                    self.loops.len()
+                ),
+            ));
+        }
+        Ok(Some(level))
+    }
+
+    // How many loops out the loop labeled `label` is, counting the innermost as 1
+    fn label_depth(&self, label: &str) -> Option<usize> {
+        self.loops
+            .iter()
+            .rev()
+            .position(|l| l.as_deref() == Some(label))
+            .map(|i| i + 1)
     }
 
-    fn for_statement(&mut self) -> Stmt {
-        _ = self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.");
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        let label = self.next_label.take();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let cond = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after while condition")?;
+
+        let body = self.loop_body(label)?;
+
+        Ok(Stmt::While {
+            cond,
+            body: Box::new(body),
+            increment: None,
+        })
+    }
+
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        let label = self.next_label.take();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        if self.check(&TokenType::Var) && self.check_ahead(2, &TokenType::In) {
+            return self.for_in_statement(label);
+        }
+
         let initializer = if self.matches(&[TokenType::Semicolon]) {
             None
         } else if self.matches(&[TokenType::Var]) {
-            Some(self.var_declaration())
+            Some(self.var_declaration(None)?)
         } else {
-            Some(self.expression_statement())
+            Some(self.expression_statement()?)
         };
 
         let cond = if !self.check(&TokenType::Semicolon) {
-            self.expression().expect("Expect loop condition.")
+            self.expression()?
         } else {
             Expr::Literal(Literal::Boolean(true))
         };
-        _ = self.consume(&TokenType::Semicolon, "Expect ';' after loop condition.");
+        self.consume(&TokenType::Semicolon, "Expect ';' after loop condition.")?;
 
         let increment = if !self.check(&TokenType::RightParen) {
-            Some(self.expression().expect("Expect increment expression"))
+            Some(self.expression()?)
         } else {
             None
         };
-        _ = self.consume(&TokenType::RightParen, "Expect ')' after loop condition.");
-
-        let mut body = self.statement();
-        if let Some(inc) = increment {
-            body = match body {
-                Stmt::Block { mut stmts } => {
-                    stmts.push(Stmt::Expression(inc));
-                    Stmt::Block { stmts }
-                }
-                other => Stmt::Block {
-                    stmts: vec![other, Stmt::Expression(inc)],
-                },
-            }
-        }
+        self.consume(&TokenType::RightParen, "Expect ')' after loop condition.")?;
+
+        let body = self.loop_body(label)?;
 
-        body = Stmt::While {
+        // The increment isn't appended to the body: `continue` would skip it
+        let mut body = Stmt::While {
             cond,
             body: Box::new(body),
+            increment,
         };
 
         if let Some(init) = initializer {
@@ -514,46 +1085,65 @@ impl Parser {
                 stmts: vec![init, body],
             };
         }
-        body
+        Ok(body)
     }
 
-    fn if_statement(&mut self) -> Stmt {
-        _ = self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.");
-        let cond = self.expression().unwrap();
-        _ = self.consume(&TokenType::RightParen, "Expect ')' after if condition.");
+    // `for (var x in iterable) body`, the opening paren is already consumed
+    fn for_in_statement(&mut self, label: Option<String>) -> ParseResult<Stmt> {
+        self.consume(&TokenType::Var, "Expect 'var' in for-in loop.")?;
+        let name = self
+            .consume(&TokenType::Identifier, "Expect loop variable name.")?
+            .clone();
+        self.consume(&TokenType::In, "Expect 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after for-in clause.")?;
 
-        let then_br = self.statement();
+        let body = self.loop_body(label)?;
+
+        Ok(Stmt::ForIn {
+            name,
+            iterable,
+            body: Box::new(body),
+        })
+    }
+
+    fn if_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let cond = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_br = self.statement()?;
         let mut else_br = None;
         if self.matches(&[TokenType::Else]) {
-            else_br = Some(Box::new(self.statement()));
+            else_br = Some(Box::new(self.statement()?));
         }
 
-        Stmt::If {
+        Ok(Stmt::If {
             cond,
             then_br: Box::new(then_br),
             else_br,
-        }
+        })
     }
 
-    fn block(&mut self) -> Vec<Stmt> {
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
         let mut statements: Vec<Stmt> = Vec::new();
         while !self.check(&TokenType::RightBrace) && !self.is_eof() {
-            statements.push(self.declaration());
+            statements.push(self.declaration()?);
         }
-        _ = self.consume(&TokenType::RightBrace, "Expect '}' after block.");
-        statements
+        self.consume(&TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
     }
 
-    fn expression_statement(&mut self) -> Stmt {
-        let expression = self.expression().unwrap();
-        _ = self.consume(&TokenType::Semicolon, "Expect ';' after expression.");
-        Stmt::Expression(expression)
+    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expression = self.expression()?;
+        self.consume(&TokenType::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression(expression))
     }
 
-    fn print_statement(&mut self) -> Stmt {
-        let value = self.expression().unwrap();
-        _ = self.consume(&TokenType::Semicolon, "Expect ';' after value.");
-        Stmt::Print(value)
+    fn print_statement(&mut self) -> ParseResult<Stmt> {
+        let value = self.expression()?;
+        self.consume(&TokenType::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print(value))
     }
 }
 
@@ -565,7 +1155,7 @@ impl Parser {
             if self.prev().type_ == TokenType::Semicolon {
                 return;
             }
-            if !matches!(
+            if matches!(
                 self.peek().type_,
                 TokenType::Class
                     | TokenType::Fun
@@ -576,8 +1166,9 @@ impl Parser {
                     | TokenType::Print
                     | TokenType::Return
             ) {
-                self.advance();
+                return;
             }
+            self.advance();
         }
     }
 }
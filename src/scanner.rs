@@ -1,5 +1,13 @@
 // Scanner reads provided string and returns tokens instead.
 
+use std::collections::VecDeque;
+
+use unicode_normalization::UnicodeNormalization;
+
+#[cfg(feature = "bigint")]
+use crate::bigint::BigInt;
+use crate::err::LoxError;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenType {
     // Single-character tokens
@@ -7,13 +15,21 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokkens
     Bang,
@@ -24,10 +40,26 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    StarStar,
+    TildeSlash,
+    Arrow,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    PlusPlus,
+    MinusMinus,
+    Question,
+    QuestionQuestion,
 
     // Literals
     Identifier,
     String,
+    // A string segment followed by `${`, see `Scanner::string`
+    Interpolation,
     Number,
 
     // Keywords
@@ -38,6 +70,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -48,6 +81,7 @@ pub enum TokenType {
     Var,
     While,
     Break,
+    Continue,
 
     Eof,
 }
@@ -59,6 +93,8 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<Literal>,
     pub line: usize,
+    // `///` comment lines right before the token, for doc tools
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -67,6 +103,9 @@ pub enum Literal {
     Nil,
     String(String),
     Integer(i64),
+    // Integer literals too large for an i64
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
     Float(f64),
 }
 
@@ -77,12 +116,22 @@ impl Token {
             lexeme,
             literal,
             line,
+            doc: None,
         }
     }
 }
 
+// Produces tokens lazily as an `Iterator`, ending with `Eof`. Scan errors
+// are yielded in place and scanning goes on after them.
 pub struct Scanner {
-    pub tokens: Vec<Token>,
+    // Scanned but not yet handed out. Scanning a character yields no token
+    // (whitespace, comments), one, or an error.
+    queue: VecDeque<Result<Token, LoxError>>,
+    finished: bool,
+    // One entry per open `${`, counting the `{` opened inside it since
+    interpolations: Vec<usize>,
+    // `///` lines waiting for the next token
+    doc: Vec<String>,
 
     _source: String,
     _start: usize,
@@ -90,37 +139,105 @@ pub struct Scanner {
     _line: usize,
 }
 
+impl Iterator for Scanner {
+    type Item = Result<Token, LoxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.queue.is_empty() && !self.finished {
+            if self.is_eof() {
+                self.finish();
+            } else {
+                self._start = self._current;
+                self.scan_single_token();
+            }
+        }
+        self.queue.pop_front()
+    }
+}
+
 impl Scanner {
     pub fn new(source: String) -> Self {
-        Self {
+        let mut scanner = Self {
             _source: source,
-            tokens: Vec::new(),
+            queue: VecDeque::new(),
+            finished: false,
+            interpolations: Vec::new(),
+            doc: Vec::new(),
 
             _start: 0,
             _current: 0,
             _line: 1,
+        };
+        scanner.skip_shebang();
+        scanner
+    }
+
+    // Scans the whole source up front, reporting every error at once.
+    pub fn scan_tokens(self) -> Result<Vec<Token>, Vec<LoxError>> {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for item in self {
+            match item {
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
         }
     }
 
-    // Consumes self and return tokens
-    // Should return Result
-    pub fn scan_tokens(mut self) -> Vec<Token> {
-        while !self.is_eof() {
-            self._start = self._current;
-            self.scan_single_token();
+    fn finish(&mut self) {
+        if !self.interpolations.is_empty() {
+            self.error_at(
+                self._line,
+                "${".to_string(),
+                "Unterminated string interpolation.",
+            );
         }
+        self._start = self._current;
         self.add_token(TokenType::Eof);
-        self.tokens
+        self.finished = true;
+    }
+
+    // Reported at the lexeme scanned so far
+    fn error(&mut self, msg: impl Into<String>) {
+        self.error_at(self._line, self.get_lexeme(), msg);
+    }
+
+    fn error_at(&mut self, line: usize, where_: String, msg: impl Into<String>) {
+        self.queue.push_back(Err(LoxError::new(line, where_, msg)));
+    }
+
+    // `#!/usr/bin/env rlox` on the very first line makes a script executable.
+    // The trailing '\n' is left in place, so line numbers stay correct.
+    fn skip_shebang(&mut self) {
+        if !self._source.starts_with("#!") {
+            return;
+        }
+        while self.peek() != '\n' && !self.is_eof() {
+            self.advance();
+        }
     }
 
     fn add_token(&mut self, type_: TokenType) {
         let token = Token::new(type_, self.get_lexeme(), None, self._line);
-        self.tokens.push(token);
+        self.push_token(token);
     }
 
     fn add_token_with_literal(&mut self, type_: TokenType, literal: Literal) {
         let token = Token::new(type_, self.get_lexeme(), Some(literal), self._line);
-        self.tokens.push(token);
+        self.push_token(token);
+    }
+
+    fn push_token(&mut self, mut token: Token) {
+        if !self.doc.is_empty() {
+            token.doc = Some(std::mem::take(&mut self.doc).join("\n"));
+        }
+        self.queue.push_back(Ok(token));
     }
 
     fn get_lexeme(&self) -> String {
@@ -170,22 +287,58 @@ impl Scanner {
         match ch {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(TokenType::LeftBrace)
+            }
+            // Closes the innermost `${` once its own braces are balanced
+            '}' if self.interpolations.last() == Some(&0) => {
+                self.interpolations.pop();
+                self.string();
+            }
+            '}' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth -= 1;
+                }
+                self.add_token(TokenType::RightBrace)
+            }
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
+            ':' => self.add_token(TokenType::Colon),
+            '?' => {
+                self.add_conditional_token('?', TokenType::QuestionQuestion, TokenType::Question)
+            }
             '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
+            '-' if self.match_char('-') => self.add_token(TokenType::MinusMinus),
+            '-' => self.add_conditional_token('=', TokenType::MinusEqual, TokenType::Minus),
+            '+' if self.match_char('+') => self.add_token(TokenType::PlusPlus),
+            '+' => self.add_conditional_token('=', TokenType::PlusEqual, TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
+            '*' if self.match_char('*') => self.add_token(TokenType::StarStar),
+            '*' => self.add_conditional_token('=', TokenType::StarEqual, TokenType::Star),
+            '%' => self.add_conditional_token('=', TokenType::PercentEqual, TokenType::Percent),
 
             '!' => self.add_conditional_token('=', TokenType::BangEqual, TokenType::Bang),
+            '=' if self.match_char('>') => self.add_token(TokenType::Arrow),
             '=' => self.add_conditional_token('=', TokenType::EqualEqual, TokenType::Equal),
+            '<' if self.match_char('<') => self.add_token(TokenType::LessLess),
             '<' => self.add_conditional_token('=', TokenType::LessEqual, TokenType::Less),
+            '>' if self.match_char('>') => self.add_token(TokenType::GreaterGreater),
             '>' => self.add_conditional_token('=', TokenType::GreaterEqual, TokenType::Greater),
+            '&' => self.add_token(TokenType::Ampersand),
+            '|' => self.add_token(TokenType::Pipe),
+            '^' => self.add_token(TokenType::Caret),
+            // `//` already starts a comment, so integer division is spelled `~/`
+            '~' => self.add_conditional_token('/', TokenType::TildeSlash, TokenType::Tilde),
 
             // Long lexemes
-            '/' => self.lookahead('/', TokenType::Slash, None),
+            '/' if self.match_char('=') => self.add_token(TokenType::SlashEqual),
+            '/' if self.match_char('/') => self.line_comment(),
+            '/' if self.match_char('*') => self.block_comment(),
+            '/' => self.add_token(TokenType::Slash),
             // Ignored
             ' ' | '\r' | '\t' => {}
 
@@ -193,70 +346,211 @@ impl Scanner {
                 self._line += 1;
             }
 
+            '"' if self._source[self._current..].starts_with("\"\"") => {
+                self._current += 2; // consume the rest of the opening """
+                self.multiline_string();
+            }
             '"' => self.string(),
+            'r' if self.peek() == '"' => {
+                self.advance();
+                self.raw_string();
+            }
 
             _ => {
                 if ch.is_ascii_digit() {
                     return self.number();
                 }
-                if ch.is_ascii_alphabetic() || ch == '_' {
+                if is_ident_start(ch) {
                     return self.identifier();
                 }
-                println!("Unexpected token: {}", ch)
+                self.error("Unexpected character.")
             }
         };
         //todo: match character
     }
 
+    // The lexeme keeps the source text, quotes and escapes included, while
+    // the literal holds the decoded value.
+    //
+    // `"a ${x} b"` scans as `Interpolation("a ")`, the tokens of `x`, then
+    // `String(" b")`: the `}` closing the expression resumes the string.
     fn string(&mut self) {
+        let start_line = self._line;
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_eof() {
-            if self.peek() == '\n' {
-                self._line += 1;
+            if self.peek() == '$' && self.peek_next() == '{' {
+                self.advance(); // consume "${"
+                self.advance();
+                self.add_token_with_literal(TokenType::Interpolation, Literal::String(value));
+                self.interpolations.push(0);
+                return;
+            }
+            match self.advance() {
+                '\\' => {
+                    if let Some(ch) = self.escape() {
+                        value.push(ch);
+                    }
+                }
+                ch => {
+                    if ch == '\n' {
+                        self._line += 1;
+                    }
+                    value.push(ch);
+                }
             }
-            self.advance();
         }
 
+        // Pointing at the opening quote beats echoing the rest of the file
         if self.is_eof() {
-            // todo: throw error
+            self.error_at(start_line, "\"".to_string(), "Unterminated string.");
             return;
         }
 
-        let val = &self._source[self._start + 1..self._current];
-        let lit = Literal::String(val.to_string());
+        self.advance();
+        self.add_token_with_literal(TokenType::String, Literal::String(value));
+    }
+
+    // `\n \t \r \\ \" \0 \$` and `\u{1F600}`, the backslash already consumed.
+    // None after reporting an invalid escape, or at the end of the source,
+    // which `string` reports as unterminated.
+    fn escape(&mut self) -> Option<char> {
+        if self.is_eof() {
+            return None;
+        }
+        let (start, line) = (self._current - 1, self._line);
+        let ch = match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '0' => Some('\0'),
+            '$' => Some('$'),
+            'u' => self.unicode_escape(),
+            other => {
+                if other == '\n' {
+                    self._line += 1;
+                }
+                None
+            }
+        };
+        if ch.is_none() {
+            let text = self._source[start..self._current].trim_end().to_string();
+            self.error_at(line, text, "Invalid escape sequence.");
+        }
+        ch
+    }
 
+    // `{` 1 to 6 hex digits `}`, naming a Unicode scalar value
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.match_char('{') {
+            return None;
+        }
+        let start = self._current;
+        while self.peek().is_ascii_hexdigit() {
+            self.advance();
+        }
+        let end = self._current;
+        if !self.match_char('}') || !(1..=6).contains(&(end - start)) {
+            return None;
+        }
+        u32::from_str_radix(&self._source[start..end], 16)
+            .ok()
+            .and_then(char::from_u32)
+    }
+
+    // `r"C:\path"`: no escapes or interpolation, so it can't contain a `"`
+    fn raw_string(&mut self) {
+        let start_line = self._line;
+        let content_start = self._current;
+        while self.peek() != '"' && !self.is_eof() {
+            if self.advance() == '\n' {
+                self._line += 1;
+            }
+        }
+
+        if self.is_eof() {
+            self.error_at(start_line, "r\"".to_string(), "Unterminated string.");
+            return;
+        }
+
+        let value = self._source[content_start..self._current].to_string();
         self.advance();
-        self.add_token_with_literal(TokenType::String, lit);
+        self.add_token_with_literal(TokenType::String, Literal::String(value));
+    }
+
+    // `"""` ... `"""` spanning several lines. The text is verbatim like a raw
+    // string, apart from the indentation stripped by `strip_indent`.
+    fn multiline_string(&mut self) {
+        let start_line = self._line;
+        let content_start = self._current;
+        while !self._source[self._current..].starts_with("\"\"\"") {
+            if self.is_eof() {
+                self.error_at(start_line, "\"\"\"".to_string(), "Unterminated string.");
+                return;
+            }
+            if self.advance() == '\n' {
+                self._line += 1;
+            }
+        }
+
+        let value = strip_indent(&self._source[content_start..self._current]);
+        self._current += 3; // consume the closing """
+        self.add_token_with_literal(TokenType::String, Literal::String(value));
     }
 
+    // `123`, `1_000`, `1.5`, `1e-9`, `0xFF`, `0o17` and `0b1010`. Letters and
+    // digits running into the literal are part of it, so `0b102` or `12ab`
+    // are reported instead of being split into several tokens.
     fn number(&mut self) {
-        // Integer part
-        while self.peek().is_ascii_digit() {
-            self.advance();
+        let radix = match (&self._source[self._start..self._current], self.peek()) {
+            ("0", 'x' | 'X') => 16,
+            ("0", 'o' | 'O') => 8,
+            ("0", 'b' | 'B') => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.advance(); // consume the prefix letter
         }
 
-        // Look for fractional part
-        let mut is_float = false;
-        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
-            is_float = true;
-            self.advance(); // consume "."
-            while self.peek().is_ascii_digit() {
+        loop {
+            let ch = self.peek();
+            if radix == 10 && matches!(ch, 'e' | 'E') && matches!(self.peek_next(), '+' | '-') {
+                self.advance(); // consume "e" and the sign
+                self.advance();
+            } else if ch.is_alphanumeric() || ch == '_' {
                 self.advance();
+            } else if ch == '.' && radix == 10 && self.peek_next().is_ascii_digit() {
+                self.advance(); // consume "."
+            } else {
+                break;
             }
         }
-        let val = &self._source[self._start..self._current];
-        let lit = match is_float {
-            true => Literal::Float(val.parse().unwrap()),
-            false => Literal::Integer(val.parse().unwrap()),
-        };
 
-        self.add_token_with_literal(TokenType::Number, lit);
+        let text = &self._source[self._start..self._current];
+        // Skip "0x", "0o" or "0b"
+        let digits = if radix == 10 { text } else { &text[2..] };
+        match number_literal(digits, radix) {
+            Ok(lit) => self.add_token_with_literal(TokenType::Number, lit),
+            Err(msg) => self.error(msg),
+        }
     }
 
+    // Identifiers follow UAX #31: `_` or XID_Start, then any XID_Continue.
+    // The lexeme is NFC normalized, so `é` typed precomposed or as `e` plus
+    // a combining accent names the same variable.
     fn identifier(&mut self) {
-        while self.peek().is_alphanumeric() {
+        while unicode_ident::is_xid_continue(self.peek()) {
             self.advance();
         }
-        let token = match &self._source[self._start..self._current] {
+        let text = &self._source[self._start..self._current];
+        let name: String = if text.is_ascii() {
+            text.to_string()
+        } else {
+            text.nfc().collect()
+        };
+
+        let token = match name.as_str() {
             "and" => Some(TokenType::And),
             "class" => Some(TokenType::Class),
             "else" => Some(TokenType::Else),
@@ -264,6 +558,7 @@ impl Scanner {
             "for" => Some(TokenType::For),
             "fun" => Some(TokenType::Fun),
             "if" => Some(TokenType::If),
+            "in" => Some(TokenType::In),
             "nil" => Some(TokenType::Nil),
             "while" => Some(TokenType::While),
             "or" => Some(TokenType::Or),
@@ -274,13 +569,12 @@ impl Scanner {
             "true" => Some(TokenType::True),
             "var" => Some(TokenType::Var),
             "break" => Some(TokenType::Break),
+            "continue" => Some(TokenType::Continue),
             _ => None,
         };
 
-        match token {
-            Some(t) => self.add_token(t),
-            None => self.add_token(TokenType::Identifier),
-        };
+        let type_ = token.unwrap_or(TokenType::Identifier);
+        self.push_token(Token::new(type_, name, None, self._line));
     }
 
     fn add_conditional_token(&mut self, expect: char, if_match: TokenType, if_not: TokenType) {
@@ -292,18 +586,248 @@ impl Scanner {
         self.add_token(t);
     }
 
-    // For Predictive Parsing
-    fn lookahead(&mut self, expect: char, on_found: TokenType, _escape: Option<char>) {
-        // todo: handle multiline comments /* ... */
-        let esc = _escape.unwrap_or('\n');
-        let next = self.match_char(expect);
+    // `// ...` is skipped. `/// ...` is documentation, kept for the next token.
+    fn line_comment(&mut self) {
+        let is_doc = self.peek() == '/' && self.peek_next() != '/';
+        while self.peek() != '\n' && !self.is_eof() {
+            self.advance();
+        }
+        if is_doc {
+            let text = &self._source[self._start + 3..self._current];
+            let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+            self.doc.push(text.to_string());
+        }
+    }
 
-        if next {
-            while self.peek() != esc && !self.is_eof() {
-                self.advance();
+    // `/* ... */`. They nest, so code holding a block comment can itself be
+    // commented out.
+    fn block_comment(&mut self) {
+        let start_line = self._line;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_eof() {
+                self.error_at(start_line, "/*".to_string(), "Unterminated block comment.");
+                return;
             }
-        } else {
-            self.add_token(on_found);
+            match self.advance() {
+                '\n' => self._line += 1,
+                '/' if self.match_char('*') => depth += 1,
+                '*' if self.match_char('/') => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
+}
+
+// Drops a line break right after the opening quotes and a last line holding
+// only the closing ones, then the indentation all non-blank lines share:
+//
+//     var sql = """
+//         SELECT *
+//           FROM t
+//         """;
+//
+// is "SELECT *\n  FROM t".
+fn strip_indent(text: &str) -> String {
+    let text = text
+        .strip_prefix('\n')
+        .or_else(|| text.strip_prefix("\r\n"))
+        .unwrap_or(text);
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() > 1 && lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let is_blank = |line: &str| line.trim().is_empty();
+    let indent = lines
+        .iter()
+        .filter(|l| !is_blank(l))
+        .map(|l| l.len() - l.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+
+    let lines: Vec<&str> = lines
+        .into_iter()
+        .map(|l| if is_blank(l) { "" } else { &l[indent..] })
+        .collect();
+    lines.join("\n")
+}
+
+fn number_literal(digits: &str, radix: u32) -> Result<Literal, String> {
+    let kind = match radix {
+        16 => "hexadecimal",
+        8 => "octal",
+        2 => "binary",
+        _ => "decimal",
+    };
+    if digits.is_empty() {
+        return Err(format!("Missing digits in {kind} literal."));
+    }
+    check_separators(digits, radix)?;
+    let digits = digits.replace('_', "");
+
+    // Only decimal literals can have a fraction or an exponent
+    let is_float = radix == 10 && digits.contains(['.', 'e', 'E']);
+    if is_float {
+        return float_literal(&digits);
+    }
+    if let Some(bad) = digits.chars().find(|c| !c.is_digit(radix)) {
+        return Err(format!("Invalid digit '{bad}' in {kind} literal."));
+    }
+    integer_literal(&digits, radix).ok_or_else(|| "Integer literal is too large.".to_string())
+}
+
+// A `_` only ever sits between two digits: not `_1`, `1_`, `1__0` or `1_.5`
+fn check_separators(digits: &str, radix: u32) -> Result<(), String> {
+    let chars: Vec<char> = digits.chars().collect();
+    for (i, ch) in chars.iter().enumerate() {
+        if *ch != '_' {
+            continue;
+        }
+        let before = i.checked_sub(1).map(|i| chars[i]);
+        let after = chars.get(i + 1);
+        if !before.is_some_and(|c| c.is_digit(radix)) || !after.is_some_and(|c| c.is_digit(radix)) {
+            return Err("Digit separator '_' must be between digits.".to_string());
+        }
+    }
+    Ok(())
+}
+
+// `digits[.digits][(e|E)[+|-]digits]`, separators already removed
+fn float_literal(text: &str) -> Result<Literal, String> {
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (text, None),
+    };
+    let (int, fract) = mantissa.split_once('.').unwrap_or((mantissa, "0"));
+    let all_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+
+    if !all_digits(int) || !all_digits(fract) {
+        return Err("Malformed number literal.".to_string());
+    }
+    if let Some(exponent) = exponent {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if !all_digits(exponent) {
+            return Err("Missing digits in exponent.".to_string());
+        }
+    }
+    text.parse()
+        .map(Literal::Float)
+        .map_err(|_| "Malformed number literal.".to_string())
+}
+
+// None when the value doesn't fit an i64, and there is no `bigint` to fall back to
+#[cfg(feature = "bigint")]
+fn integer_literal(digits: &str, radix: u32) -> Option<Literal> {
+    match i64::from_str_radix(digits, radix) {
+        Ok(n) => Some(Literal::Integer(n)),
+        Err(_) => BigInt::parse_bytes(digits.as_bytes(), radix).map(Literal::BigInt),
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+fn integer_literal(digits: &str, radix: u32) -> Option<Literal> {
+    i64::from_str_radix(digits, radix)
+        .ok()
+        .map(Literal::Integer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(digits: &str, radix: u32) -> i64 {
+        match number_literal(digits, radix) {
+            Ok(Literal::Integer(n)) => n,
+            other => panic!("{digits} in radix {radix}: {other:?}"),
+        }
+    }
+
+    fn float(digits: &str) -> f64 {
+        match number_literal(digits, 10) {
+            Ok(Literal::Float(n)) => n,
+            other => panic!("{digits}: {other:?}"),
+        }
+    }
+
+    fn error(digits: &str, radix: u32) -> String {
+        match number_literal(digits, radix) {
+            Err(msg) => msg,
+            Ok(lit) => panic!("{digits} in radix {radix} scanned as {lit:?}"),
+        }
+    }
+
+    #[test]
+    fn separators_sit_between_digits() {
+        assert!(check_separators("1_000_000", 10).is_ok());
+        assert!(check_separators("ff_ff", 16).is_ok());
+        assert!(check_separators("1_0.0_1e1_0", 10).is_ok());
+        for bad in ["_1", "1_", "1__0", "1_.5", "1._5", "1_e5", "1e_5"] {
+            assert!(check_separators(bad, 10).is_err(), "{bad}");
+        }
+        // `2` isn't a binary digit, so it can't anchor a separator
+        assert!(check_separators("1_2", 2).is_err());
+    }
+
+    #[test]
+    fn integer_literals() {
+        assert_eq!(int("0", 10), 0);
+        assert_eq!(int("1_000", 10), 1000);
+        assert_eq!(int("ff", 16), 255);
+        assert_eq!(int("DEAD_beef", 16), 0xdead_beef);
+        assert_eq!(int("17", 8), 15);
+        assert_eq!(int("1010", 2), 10);
+        assert_eq!(int("9223372036854775807", 10), i64::MAX);
+        assert_eq!(int("7fff_ffff_ffff_ffff", 16), i64::MAX);
+    }
+
+    #[test]
+    fn float_literals() {
+        assert_eq!(float("1.5"), 1.5);
+        assert_eq!(float("1e3"), 1000.0);
+        assert_eq!(float("2.5E-1"), 0.25);
+        assert_eq!(float("1_0.2_5e+1"), 102.5);
+    }
+
+    #[test]
+    fn malformed_literals() {
+        assert_eq!(error("", 16), "Missing digits in hexadecimal literal.");
+        assert_eq!(error("12", 2), "Invalid digit '2' in binary literal.");
+        assert_eq!(error("8", 8), "Invalid digit '8' in octal literal.");
+        assert_eq!(error("1g", 16), "Invalid digit 'g' in hexadecimal literal.");
+        assert_eq!(error("12abc", 10), "Invalid digit 'a' in decimal literal.");
+        assert_eq!(error("1e", 10), "Missing digits in exponent.");
+        assert_eq!(error("1e+", 10), "Missing digits in exponent.");
+        assert_eq!(error("1.5.5", 10), "Malformed number literal.");
+        assert_eq!(
+            error("1__0", 10),
+            "Digit separator '_' must be between digits."
+        );
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    #[test]
+    fn integer_literal_overflow() {
+        assert_eq!(
+            error("9223372036854775808", 10),
+            "Integer literal is too large."
+        );
+        assert_eq!(
+            error("1_0000_0000_0000_0000", 16),
+            "Integer literal is too large."
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn integer_literal_overflow_promotes() {
+        match number_literal("1_0000_0000_0000_0000", 16) {
+            Ok(Literal::BigInt(n)) => assert_eq!(n, BigInt::from(1u128 << 64)),
+            other => panic!("{other:?}"),
         }
     }
 }
@@ -0,0 +1,215 @@
+// Built-in functions available to every script, registered through
+// `Interpreter::register_native`.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+#[cfg(feature = "bigint")]
+use crate::bigint;
+use crate::{
+    callable::{NativeFunction, key_arg, list_arg, map_arg, native_error, number_arg},
+    interpreter::{Interpreter, MapKey, RuntimeError, Value},
+};
+
+pub fn install(interp: &mut Interpreter) {
+    install_lists(interp);
+    install_maps(interp);
+    install_iterators(interp);
+    install_callbacks(interp);
+}
+
+fn install_lists(interp: &mut Interpreter) {
+    // `len(x)` works on lists, maps and strings (counted in characters)
+    interp.register_native("len", 1, |_, args| match &args[0] {
+        Value::List(list) => Ok(Value::Int(list.borrow().len() as i64)),
+        Value::Map(map) => Ok(Value::Int(map.borrow().len() as i64)),
+        Value::Str(s) => Ok(Value::Int(s.chars().count() as i64)),
+        other => Err(native_error(
+            "len",
+            format!("Can't take length of {}.", other.type_name()),
+        )),
+    });
+
+    // `push(list, value)` appends in place
+    interp.register_native("push", 2, |_, mut args| {
+        let list = list_arg("push", &args, 0)?;
+        list.borrow_mut().push(args.pop().unwrap_or_default());
+        Ok(Value::Nil)
+    });
+
+    // `pop(list)` removes and returns the last element, nil when empty
+    interp.register_native("pop", 1, |_, args| {
+        let list = list_arg("pop", &args, 0)?;
+        let last = list.borrow_mut().pop();
+        Ok(last.unwrap_or_default())
+    });
+}
+
+fn install_maps(interp: &mut Interpreter) {
+    // Keys and values come out in insertion order
+    interp.register_native("keys", 1, |_, args| {
+        let map = map_arg("keys", &args, 0)?;
+        let keys = map.borrow().keys().map(MapKey::to_value).collect();
+        Ok(Value::list(keys))
+    });
+
+    interp.register_native("values", 1, |_, args| {
+        let map = map_arg("values", &args, 0)?;
+        let values = map.borrow().values().cloned().collect();
+        Ok(Value::list(values))
+    });
+
+    interp.register_native("has", 2, |_, args| {
+        let map = map_arg("has", &args, 0)?;
+        let key = key_arg("has", &args, 1)?;
+        Ok(Value::Bool(map.borrow().contains_key(&key)))
+    });
+
+    // `remove(map, key)` deletes the entry and returns its value, nil when absent
+    interp.register_native("remove", 2, |_, args| {
+        let map = map_arg("remove", &args, 0)?;
+        let key = key_arg("remove", &args, 1)?;
+        let removed = map.borrow_mut().shift_remove(&key);
+        Ok(removed.unwrap_or_default())
+    });
+}
+
+fn install_iterators(interp: &mut Interpreter) {
+    // `range(a, b, step)` is lazy: it returns an iterator function producing
+    // a, a + step, ... up to, but excluding, b. A negative step counts down.
+    interp.register_native("range", 3, |_, args| {
+        // Ints produce ints, big ones included, any float argument makes the
+        // whole range floats
+        let iter = match (&args[0], &args[1], &args[2]) {
+            (Value::Int(start), Value::Int(end), Value::Int(step)) => {
+                range_iterator(*start, *end, *step, i64::checked_add, Value::Int)?
+            }
+            _ => {
+                #[cfg(feature = "bigint")]
+                if let [Some(start), Some(end), Some(step)] =
+                    [0, 1, 2].map(|i| bigint::as_big(&args[i]))
+                {
+                    let iter =
+                        range_iterator(start, end, step, |a, b| Some(a + b), bigint::demote)?;
+                    return Ok(Value::Native(Rc::new(iter)));
+                }
+                let start = number_arg("range", &args, 0)?;
+                let end = number_arg("range", &args, 1)?;
+                let step = number_arg("range", &args, 2)?;
+                range_iterator(start, end, step, |a, b| Some(a + b), Value::Number)?
+            }
+        };
+        Ok(Value::Native(Rc::new(iter)))
+    });
+}
+
+// `add` returns None on overflow, which ends the range
+fn range_iterator<T>(
+    start: T,
+    end: T,
+    step: T,
+    add: fn(T, T) -> Option<T>,
+    into: fn(T) -> Value,
+) -> Result<NativeFunction, RuntimeError>
+where
+    T: Clone + PartialOrd + Default + 'static,
+{
+    let zero = T::default();
+    if step == zero {
+        return Err(native_error("range", "Step can't be zero."));
+    }
+
+    let current = Cell::new(Some(start));
+    Ok(NativeFunction::new("range iterator", 0, move |_, _| {
+        let Some(n) = current.take() else {
+            return Ok(Value::Nil);
+        };
+        let done = if step > zero { n >= end } else { n <= end };
+        if done {
+            return Ok(Value::Nil);
+        }
+        current.set(add(n.clone(), step.clone()));
+        Ok(into(n))
+    }))
+}
+
+// Natives taking a Lox function and calling back into the interpreter
+fn install_callbacks(interp: &mut Interpreter) {
+    // `map(list, f)` returns a new list of `f(x)` for every element
+    interp.register_native("map", 2, |interp, args| {
+        let list = list_arg("map", &args, 0)?;
+        let items = list.borrow().clone();
+        let mut out = Vec::with_capacity(items.len());
+        for item in items {
+            out.push(interp.call_value(&args[1], vec![item])?);
+        }
+        Ok(Value::list(out))
+    });
+
+    // `filter(list, f)` returns a new list of the elements `f` holds true for
+    interp.register_native("filter", 2, |interp, args| {
+        let list = list_arg("filter", &args, 0)?;
+        let items = list.borrow().clone();
+        let mut out = Vec::new();
+        for item in items {
+            if interp.call_value(&args[1], vec![item.clone()])?.is_truthy() {
+                out.push(item);
+            }
+        }
+        Ok(Value::list(out))
+    });
+
+    // `sort(list, cmp)` returns a new, stably sorted list. `cmp(a, b)` returns
+    // a negative number when a goes first, a positive one when b does, 0 otherwise.
+    interp.register_native("sort", 2, |interp, args| {
+        let list = list_arg("sort", &args, 0)?;
+        let items = list.borrow().clone();
+        let sorted = merge_sort(items, &mut |a, b| match interp
+            .call_value(&args[1], vec![a.clone(), b.clone()])?
+        {
+            Value::Int(n) => Ok(n.cmp(&0)),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => Ok(bigint::cmp_zero(&n)),
+            Value::Number(n) => Ok(n.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+            other => Err(native_error(
+                "sort",
+                format!(
+                    "Comparator must return a number, got {}.",
+                    other.type_name()
+                ),
+            )),
+        })?;
+        Ok(Value::list(sorted))
+    });
+}
+
+// Stable top-down merge sort. Unlike `sort_by` it stops at the first
+// comparator error and never panics on an inconsistent ordering.
+fn merge_sort(
+    mut items: Vec<Value>,
+    cmp: &mut impl FnMut(&Value, &Value) -> Result<Ordering, RuntimeError>,
+) -> Result<Vec<Value>, RuntimeError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, cmp)?;
+    let right = merge_sort(right, cmp)?;
+
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Ties keep the left element first, which is what makes it stable
+        let next = if cmp(l, r)? == Ordering::Greater {
+            right.next()
+        } else {
+            left.next()
+        };
+        out.extend(next);
+    }
+    out.extend(left);
+    out.extend(right);
+    Ok(out)
+}